@@ -0,0 +1,42 @@
+// Sums the log luminance of every row of the HDR scene, one invocation per row, see luminance.rs
+[[block]]
+struct Params {
+    width: u32;
+    height: u32;
+};
+
+[[block]]
+struct RowSums {
+    data: [[stride(4)]] array<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> params: Params;
+
+[[group(0), binding(1)]]
+var t_scene: texture_2d<f32>;
+
+[[group(0), binding(2)]]
+var<storage> rows: [[access(read_write)]] RowSums;
+
+// Keeps black pixels from pulling the log average down to minus infinity
+let MIN_LUMINANCE: f32 = 0.0001;
+
+[[stage(compute), workgroup_size(64, 1, 1)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    if (id.x >= params.height) {
+        return;
+    }
+    var sum: f32 = 0.0;
+    var x: u32 = 0u;
+    loop {
+        if (x >= params.width) {
+            break;
+        }
+        let color = textureLoad(t_scene, vec2<i32>(i32(x), i32(id.x)), 0).rgb;
+        let luminance = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+        sum = sum + log(max(luminance, MIN_LUMINANCE));
+        x = x + 1u;
+    }
+    rows.data[id.x] = sum;
+}
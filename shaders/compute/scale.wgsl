@@ -0,0 +1,25 @@
+// Multiplies the first count values of a buffer by factor, in place
+[[block]]
+struct Params {
+    factor: u32;
+    count: u32;
+};
+
+[[block]]
+struct Values {
+    data: [[stride(4)]] array<u32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> params: Params;
+
+[[group(0), binding(1)]]
+var<storage> values: [[access(read_write)]] Values;
+
+[[stage(compute), workgroup_size(64, 1, 1)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    if (id.x >= params.count) {
+        return;
+    }
+    values.data[id.x] = values.data[id.x] * params.factor;
+}
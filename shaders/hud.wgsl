@@ -0,0 +1,40 @@
+struct VertexInput {
+    [[location(0)]] position: vec2<f32>;
+    [[location(1)]] tex_coords: vec2<f32>;
+    [[location(2)]] color: vec4<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+    [[location(1)]] color: vec4<f32>;
+};
+
+[[block]]
+struct Uniforms {
+    screen_size: vec2<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> uniforms: Uniforms;
+
+// Positions are in pixels with the origin in the top left corner of the screen
+[[stage(vertex)]]
+fn main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    let ndc = in.position / uniforms.screen_size * 2.0 - vec2<f32>(1.0, 1.0);
+    out.clip_position = vec4<f32>(ndc.x, -ndc.y, 0.0, 1.0);
+    out.tex_coords = in.tex_coords;
+    out.color = in.color;
+    return out;
+}
+
+[[group(1), binding(0)]]
+var t_glyphs: texture_2d<f32>;
+[[group(1), binding(1)]]
+var s_glyphs: sampler;
+
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let coverage = textureSample(t_glyphs, s_glyphs, in.tex_coords).a;
+    return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
@@ -0,0 +1,60 @@
+// Integrates the GGX specular BRDF for the split sum approximation. x is n dot v and y the
+// roughness, the result is the scale (r) and bias (g) applied to the surface's F0.
+[[group(0), binding(0)]]
+var output: [[access(write)]] texture_storage_2d<rgba16float>;
+
+#include "common.wgsl"
+
+let SAMPLE_COUNT: u32 = 512u;
+
+// Around the z axis
+fn importance_sample_ggx(xi: vec2<f32>, roughness: f32) -> vec3<f32> {
+    let a = roughness * roughness;
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    return vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+}
+
+fn geometry_schlick_ggx(n_dot_v: f32, roughness: f32) -> f32 {
+    // k for image based lighting
+    let k = roughness * roughness / 2.0;
+    return n_dot_v / (n_dot_v * (1.0 - k) + k);
+}
+
+[[stage(compute), workgroup_size(8, 8, 1)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let size = textureDimensions(output);
+    if (id.x >= u32(size.x) || id.y >= u32(size.y)) {
+        return;
+    }
+    let n_dot_v = max((f32(id.x) + 0.5) / f32(size.x), 0.001);
+    let roughness = (f32(id.y) + 0.5) / f32(size.y);
+    let v = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+
+    var scale: f32 = 0.0;
+    var bias: f32 = 0.0;
+    var i: u32 = 0u;
+    loop {
+        if (i >= SAMPLE_COUNT) {
+            break;
+        }
+        let xi = vec2<f32>(f32(i) / f32(SAMPLE_COUNT), radical_inverse(i));
+        let h = importance_sample_ggx(xi, roughness);
+        let l = normalize(2.0 * dot(v, h) * h - v);
+        let n_dot_l = max(l.z, 0.0);
+        let n_dot_h = max(h.z, 0.0);
+        let v_dot_h = max(dot(v, h), 0.0);
+        if (n_dot_l > 0.0) {
+            let g = geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness);
+            let g_vis = g * v_dot_h / (n_dot_h * n_dot_v);
+            let fc = pow(1.0 - v_dot_h, 5.0);
+            scale = scale + (1.0 - fc) * g_vis;
+            bias = bias + fc * g_vis;
+        }
+        i = i + 1u;
+    }
+
+    let result = vec2<f32>(scale, bias) / f32(SAMPLE_COUNT);
+    textureStore(output, vec2<i32>(i32(id.x), i32(id.y)), vec4<f32>(result, 0.0, 1.0));
+}
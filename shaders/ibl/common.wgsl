@@ -0,0 +1,43 @@
+// Shared by the image based lighting shaders
+
+let PI: f32 = 3.14159265359;
+
+// Direction through texel coordinates uv in 0..1 of a cube face, the same convention as
+// texture::cube_face_direction on the CPU
+fn cube_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    let s = uv.x * 2.0 - 1.0;
+    let t = uv.y * 2.0 - 1.0;
+    var direction: vec3<f32>;
+    if (face == 0u) {
+        direction = vec3<f32>(1.0, -t, -s);
+    } elseif (face == 1u) {
+        direction = vec3<f32>(-1.0, -t, s);
+    } elseif (face == 2u) {
+        direction = vec3<f32>(s, 1.0, t);
+    } elseif (face == 3u) {
+        direction = vec3<f32>(s, -1.0, -t);
+    } elseif (face == 4u) {
+        direction = vec3<f32>(s, -t, 1.0);
+    } else {
+        direction = vec3<f32>(-s, -t, -1.0);
+    }
+    return normalize(direction);
+}
+
+// Van der Corput sequence, the second coordinate of the Hammersley point set
+fn radical_inverse(i: u32) -> f32 {
+    var bits: u32 = i;
+    var result: f32 = 0.0;
+    var f: f32 = 0.5;
+    loop {
+        if (bits == 0u) {
+            break;
+        }
+        if ((bits & 1u) == 1u) {
+            result = result + f;
+        }
+        f = f * 0.5;
+        bits = bits >> 1u;
+    }
+    return result;
+}
@@ -0,0 +1,56 @@
+// Convolves the environment over the hemisphere around every direction, giving the diffuse
+// light arriving at a surface with that normal. One invocation per output texel, z is the face.
+[[group(0), binding(0)]]
+var t_environment: texture_cube<f32>;
+[[group(0), binding(1)]]
+var s_environment: sampler;
+[[group(0), binding(2)]]
+var output: [[access(write)]] texture_storage_2d_array<rgba16float>;
+
+#include "common.wgsl"
+
+// Angle between samples in radians
+let SAMPLE_DELTA: f32 = 0.05;
+
+[[stage(compute), workgroup_size(8, 8, 1)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let size = textureDimensions(output);
+    if (id.x >= u32(size.x) || id.y >= u32(size.y)) {
+        return;
+    }
+    let uv = (vec2<f32>(f32(id.x), f32(id.y)) + vec2<f32>(0.5, 0.5)) / vec2<f32>(f32(size.x), f32(size.y));
+    let normal = cube_direction(id.z, uv);
+
+    var up: vec3<f32> = vec3<f32>(0.0, 1.0, 0.0);
+    if (abs(normal.y) > 0.999) {
+        up = vec3<f32>(0.0, 0.0, 1.0);
+    }
+    let right = normalize(cross(up, normal));
+    let tangent_up = cross(normal, right);
+
+    var irradiance: vec3<f32> = vec3<f32>(0.0, 0.0, 0.0);
+    var count: f32 = 0.0;
+    var phi: f32 = 0.0;
+    loop {
+        if (phi >= 2.0 * PI) {
+            break;
+        }
+        var theta: f32 = 0.0;
+        loop {
+            if (theta >= 0.5 * PI) {
+                break;
+            }
+            let tangent_sample = vec3<f32>(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            let direction = tangent_sample.x * right + tangent_sample.y * tangent_up + tangent_sample.z * normal;
+            let radiance = textureSampleLevel(t_environment, s_environment, direction, 0.0).rgb;
+            irradiance = irradiance + radiance * cos(theta) * sin(theta);
+            count = count + 1.0;
+            theta = theta + SAMPLE_DELTA;
+        }
+        phi = phi + SAMPLE_DELTA;
+    }
+
+    // Scaled so a uniform environment of 1 gives an irradiance of 1
+    irradiance = PI * irradiance / count;
+    textureStore(output, vec2<i32>(i32(id.x), i32(id.y)), i32(id.z), vec4<f32>(irradiance, 1.0));
+}
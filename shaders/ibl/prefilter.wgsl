@@ -0,0 +1,67 @@
+// Prefilters the environment for specular reflections, one mip level per dispatch with the
+// roughness growing with the level. Uses GGX importance sampling with the split sum approximation.
+[[group(0), binding(0)]]
+var t_environment: texture_cube<f32>;
+[[group(0), binding(1)]]
+var s_environment: sampler;
+[[group(0), binding(2)]]
+var output: [[access(write)]] texture_storage_2d_array<rgba16float>;
+
+[[block]]
+struct Params {
+    roughness: f32;
+};
+[[group(0), binding(3)]]
+var<uniform> params: Params;
+
+#include "common.wgsl"
+
+let SAMPLE_COUNT: u32 = 256u;
+
+fn importance_sample_ggx(xi: vec2<f32>, normal: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let a = roughness * roughness;
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    let h = vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+
+    var up: vec3<f32> = vec3<f32>(0.0, 0.0, 1.0);
+    if (abs(normal.z) > 0.999) {
+        up = vec3<f32>(1.0, 0.0, 0.0);
+    }
+    let tangent = normalize(cross(up, normal));
+    let bitangent = cross(normal, tangent);
+    return normalize(tangent * h.x + bitangent * h.y + normal * h.z);
+}
+
+[[stage(compute), workgroup_size(8, 8, 1)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let size = textureDimensions(output);
+    if (id.x >= u32(size.x) || id.y >= u32(size.y)) {
+        return;
+    }
+    let uv = (vec2<f32>(f32(id.x), f32(id.y)) + vec2<f32>(0.5, 0.5)) / vec2<f32>(f32(size.x), f32(size.y));
+    // Assumes the view direction equals the normal, which loses the stretched reflections at
+    // grazing angles but makes the result independent of the view
+    let normal = cube_direction(id.z, uv);
+
+    var color: vec3<f32> = vec3<f32>(0.0, 0.0, 0.0);
+    var weight: f32 = 0.0;
+    var i: u32 = 0u;
+    loop {
+        if (i >= SAMPLE_COUNT) {
+            break;
+        }
+        let xi = vec2<f32>(f32(i) / f32(SAMPLE_COUNT), radical_inverse(i));
+        let h = importance_sample_ggx(xi, normal, params.roughness);
+        let l = normalize(2.0 * dot(normal, h) * h - normal);
+        let n_dot_l = dot(normal, l);
+        if (n_dot_l > 0.0) {
+            color = color + textureSampleLevel(t_environment, s_environment, l, 0.0).rgb * n_dot_l;
+            weight = weight + n_dot_l;
+        }
+        i = i + 1u;
+    }
+
+    textureStore(output, vec2<i32>(i32(id.x), i32(id.y)), i32(id.z), vec4<f32>(color / max(weight, 0.0001), 1.0));
+}
@@ -0,0 +1,43 @@
+// Writes the id of every object into an R32Uint target for picking, see id_buffer.rs
+
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] tex_coords: vec2<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+};
+
+#include "include/object_uniforms.wgsl"
+
+// id_buffer::pick_matrix, moves the pixel under the cursor over the whole 1x1 target
+[[block]]
+struct Pick {
+    transform: mat4x4<f32>;
+};
+[[group(2), binding(0)]]
+var<uniform> pick: Pick;
+
+[[group(0), binding(0)]]
+var t_diffuse: texture_2d<f32>;
+[[group(0), binding(1)]]
+var s_diffuse: sampler;
+
+[[stage(vertex)]]
+fn main(model: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.tex_coords = model.tex_coords;
+    out.clip_position = pick.transform * uniforms.mvp * vec4<f32>(model.position, 1.0);
+    return out;
+}
+
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> [[location(0)]] u32 {
+    // Cutouts and blended objects can only be picked where they're visible, see AlphaMode::cutoff
+    if (textureSample(t_diffuse, s_diffuse, in.tex_coords).a < uniforms.alpha_cutoff) {
+        discard;
+    }
+    return uniforms.object_id;
+}
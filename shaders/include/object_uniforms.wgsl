@@ -0,0 +1,15 @@
+// scene::ObjectUniforms, bound to group 1 by every pipeline that draws the scene's objects
+[[block]]
+struct ObjectUniforms {
+    mvp: mat4x4<f32>;
+    model: mat4x4<f32>;
+    camera_position: vec4<f32>;
+    light: vec4<f32>;
+    alpha_cutoff: f32;
+    metallic: f32;
+    roughness: f32;
+    normal_scale: f32;
+    object_id: u32;
+};
+[[group(1), binding(0)]]
+var<uniform> uniforms: ObjectUniforms;
@@ -0,0 +1,74 @@
+// Bloom passes, each reading the source at group 0 and writing a full-screen triangle:
+//   prefilter:  keeps the parts of the scene brighter than the threshold
+//   downsample: blurs a mip level into the next smaller one
+//   upsample:   blurs a mip level into the next larger one, added on top of it
+//   composite:  the largest mip level scaled by the intensity, added onto the scene
+#include "../include/fullscreen.wgsl"
+
+[[group(0), binding(0)]]
+var t_source: texture_2d<f32>;
+[[group(0), binding(1)]]
+var s_source: sampler;
+
+[[block]]
+struct Params {
+    threshold: f32;
+    // Width of the soft transition below the threshold
+    knee: f32;
+    intensity: f32;
+};
+[[group(0), binding(2)]]
+var<uniform> params: Params;
+
+fn texel_size() -> vec2<f32> {
+    let size = textureDimensions(t_source, 0);
+    return vec2<f32>(1.0 / f32(size.x), 1.0 / f32(size.y));
+}
+
+// Four bilinear samples on the texel corners, averaging a 4x4 block of the source
+fn box_filter(uv: vec2<f32>) -> vec3<f32> {
+    let d = texel_size();
+    let a = textureSample(t_source, s_source, uv + vec2<f32>(-d.x, -d.y)).rgb;
+    let b = textureSample(t_source, s_source, uv + vec2<f32>(d.x, -d.y)).rgb;
+    let c = textureSample(t_source, s_source, uv + vec2<f32>(-d.x, d.y)).rgb;
+    let e = textureSample(t_source, s_source, uv + vec2<f32>(d.x, d.y)).rgb;
+    return (a + b + c + e) * 0.25;
+}
+
+[[stage(fragment)]]
+fn prefilter(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let color = box_filter(in.tex_coords);
+    let brightness = max(color.r, max(color.g, color.b));
+    var soft: f32 = clamp(brightness - params.threshold + params.knee, 0.0, 2.0 * params.knee);
+    soft = soft * soft / (4.0 * params.knee + 0.0001);
+    let contribution = max(soft, brightness - params.threshold) / max(brightness, 0.0001);
+    return vec4<f32>(color * contribution, 1.0);
+}
+
+[[stage(fragment)]]
+fn downsample(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(box_filter(in.tex_coords), 1.0);
+}
+
+// 3x3 tent filter
+[[stage(fragment)]]
+fn upsample(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let d = texel_size();
+    let uv = in.tex_coords;
+    var sum: vec3<f32> = textureSample(t_source, s_source, uv).rgb * 4.0;
+    sum = sum + textureSample(t_source, s_source, uv + vec2<f32>(-d.x, 0.0)).rgb * 2.0;
+    sum = sum + textureSample(t_source, s_source, uv + vec2<f32>(d.x, 0.0)).rgb * 2.0;
+    sum = sum + textureSample(t_source, s_source, uv + vec2<f32>(0.0, -d.y)).rgb * 2.0;
+    sum = sum + textureSample(t_source, s_source, uv + vec2<f32>(0.0, d.y)).rgb * 2.0;
+    sum = sum + textureSample(t_source, s_source, uv + vec2<f32>(-d.x, -d.y)).rgb;
+    sum = sum + textureSample(t_source, s_source, uv + vec2<f32>(d.x, -d.y)).rgb;
+    sum = sum + textureSample(t_source, s_source, uv + vec2<f32>(-d.x, d.y)).rgb;
+    sum = sum + textureSample(t_source, s_source, uv + vec2<f32>(d.x, d.y)).rgb;
+    return vec4<f32>(sum / 16.0, 1.0);
+}
+
+[[stage(fragment)]]
+fn composite(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let bloom = textureSample(t_source, s_source, in.tex_coords).rgb;
+    return vec4<f32>(bloom * params.intensity, 0.0);
+}
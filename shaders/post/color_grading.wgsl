@@ -0,0 +1,32 @@
+// Color grading with a 3D lookup table, blended with the original color by strength
+#include "../include/fullscreen.wgsl"
+
+[[group(0), binding(0)]]
+var t_input: texture_2d<f32>;
+[[group(0), binding(1)]]
+var s_input: sampler;
+
+[[block]]
+struct Params {
+    strength: f32;
+    // Size of the lookup table along each axis
+    lut_size: f32;
+};
+[[group(0), binding(2)]]
+var<uniform> params: Params;
+
+[[group(1), binding(0)]]
+var t_lut: texture_3d<f32>;
+[[group(1), binding(1)]]
+var s_lut: sampler;
+
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let color = textureSample(t_input, s_input, in.tex_coords);
+    let clamped = clamp(color.rgb, vec3<f32>(0.0, 0.0, 0.0), vec3<f32>(1.0, 1.0, 1.0));
+    // Sample at texel centers so 0 and 1 map to the first and last entry
+    let scale = (params.lut_size - 1.0) / params.lut_size;
+    let offset = 0.5 / params.lut_size;
+    let graded = textureSample(t_lut, s_lut, clamped * scale + vec3<f32>(offset, offset, offset)).rgb;
+    return vec4<f32>(mix(color.rgb, graded, vec3<f32>(params.strength, params.strength, params.strength)), color.a);
+}
@@ -0,0 +1,21 @@
+// Extra gamma adjustment on top of the sRGB encoding done by the swap chain, 1 leaves colors unchanged
+#include "../include/fullscreen.wgsl"
+
+[[group(0), binding(0)]]
+var t_input: texture_2d<f32>;
+[[group(0), binding(1)]]
+var s_input: sampler;
+
+[[block]]
+struct Params {
+    gamma: f32;
+};
+[[group(0), binding(2)]]
+var<uniform> params: Params;
+
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let color = textureSample(t_input, s_input, in.tex_coords);
+    let corrected = pow(max(color.rgb, vec3<f32>(0.0, 0.0, 0.0)), vec3<f32>(1.0, 1.0, 1.0) / params.gamma);
+    return vec4<f32>(corrected, color.a);
+}
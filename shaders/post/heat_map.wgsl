@@ -0,0 +1,38 @@
+// Colors the number of fragments drawn per pixel, counted in the red channel
+#include "../include/fullscreen.wgsl"
+
+[[group(0), binding(0)]]
+var t_input: texture_2d<f32>;
+[[group(0), binding(1)]]
+var s_input: sampler;
+
+[[block]]
+struct Params {
+    max_count: f32;
+};
+[[group(0), binding(2)]]
+var<uniform> params: Params;
+
+fn lerp(a: vec3<f32>, b: vec3<f32>, t: f32) -> vec3<f32> {
+    return a + (b - a) * t;
+}
+
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let count = textureSample(t_input, s_input, in.tex_coords).r;
+    // Black, blue, green, yellow, red and white at even steps up to max_count
+    let x = clamp(count / params.max_count, 0.0, 1.0) * 5.0;
+    var color: vec3<f32>;
+    if (x < 1.0) {
+        color = lerp(vec3<f32>(0.0, 0.0, 0.0), vec3<f32>(0.0, 0.0, 1.0), x);
+    } elseif (x < 2.0) {
+        color = lerp(vec3<f32>(0.0, 0.0, 1.0), vec3<f32>(0.0, 1.0, 0.0), x - 1.0);
+    } elseif (x < 3.0) {
+        color = lerp(vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(1.0, 1.0, 0.0), x - 2.0);
+    } elseif (x < 4.0) {
+        color = lerp(vec3<f32>(1.0, 1.0, 0.0), vec3<f32>(1.0, 0.0, 0.0), x - 3.0);
+    } else {
+        color = lerp(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(1.0, 1.0, 1.0), x - 4.0);
+    }
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,64 @@
+// Maps HDR colors into 0..1 with the Reinhard, ACES or Uncharted 2 operator
+#include "../include/fullscreen.wgsl"
+
+[[group(0), binding(0)]]
+var t_input: texture_2d<f32>;
+[[group(0), binding(1)]]
+var s_input: sampler;
+
+[[block]]
+struct Params {
+    exposure: f32;
+    // 0: Reinhard, 1: ACES, 2: Uncharted 2, see TonemapOperator
+    operator: u32;
+};
+[[group(0), binding(2)]]
+var<uniform> params: Params;
+
+fn reinhard(x: vec3<f32>) -> vec3<f32> {
+    return x / (x + vec3<f32>(1.0, 1.0, 1.0));
+}
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve
+fn aces(x: vec3<f32>) -> vec3<f32> {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    return clamp((x * (a * x + b)) / (x * (c * x + d) + e), vec3<f32>(0.0, 0.0, 0.0), vec3<f32>(1.0, 1.0, 1.0));
+}
+
+fn uncharted2_curve(x: vec3<f32>) -> vec3<f32> {
+    let a = 0.15;
+    let b = 0.50;
+    let c = 0.10;
+    let d = 0.20;
+    let e = 0.02;
+    let f = 0.30;
+    return ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f;
+}
+
+// John Hable's filmic curve, normalized so the white point maps to 1
+fn uncharted2(x: vec3<f32>) -> vec3<f32> {
+    let exposure_bias = 2.0;
+    let white = 11.2;
+    let curr = uncharted2_curve(x * exposure_bias);
+    let white_scale = vec3<f32>(1.0, 1.0, 1.0) / uncharted2_curve(vec3<f32>(white, white, white));
+    return curr * white_scale;
+}
+
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let color = textureSample(t_input, s_input, in.tex_coords);
+    let exposed = color.rgb * params.exposure;
+    var mapped: vec3<f32>;
+    if (params.operator == 1u) {
+        mapped = aces(exposed);
+    } elseif (params.operator == 2u) {
+        mapped = uncharted2(exposed);
+    } else {
+        mapped = reinhard(exposed);
+    }
+    return vec4<f32>(mapped, color.a);
+}
@@ -0,0 +1,25 @@
+// Darkens the corners of the screen
+#include "../include/fullscreen.wgsl"
+
+[[group(0), binding(0)]]
+var t_input: texture_2d<f32>;
+[[group(0), binding(1)]]
+var s_input: sampler;
+
+[[block]]
+struct Params {
+    intensity: f32;
+    // Distance from the center, in 0..1 of the half diagonal, where darkening starts
+    radius: f32;
+    softness: f32;
+};
+[[group(0), binding(2)]]
+var<uniform> params: Params;
+
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let color = textureSample(t_input, s_input, in.tex_coords);
+    let from_center = length(in.tex_coords - vec2<f32>(0.5, 0.5)) / 0.70710678;
+    let vignette = smoothStep(params.radius, params.radius + params.softness, from_center);
+    return vec4<f32>(color.rgb * (1.0 - vignette * params.intensity), color.a);
+}
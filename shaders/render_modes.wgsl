@@ -0,0 +1,107 @@
+// Debug views of the scene, see render_mode.rs
+
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] tex_coords: vec2<f32>;
+    [[location(2)]] normal: vec3<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+    [[location(1)]] world_normal: vec3<f32>;
+    // Only meaningful for triangle soups drawn without an index buffer
+    [[location(2)]] barycentric: vec3<f32>;
+};
+
+#include "include/object_uniforms.wgsl"
+
+[[group(0), binding(0)]]
+var t_diffuse: texture_2d<f32>;
+[[group(0), binding(1)]]
+var s_diffuse: sampler;
+
+[[block]]
+struct Params {
+    // The camera's clip planes, for linearizing depth
+    near: f32;
+    far: f32;
+};
+[[group(2), binding(0)]]
+var<uniform> params: Params;
+
+[[stage(vertex)]]
+fn main(model: VertexInput, [[builtin(vertex_index)]] index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    out.tex_coords = model.tex_coords;
+    out.world_normal = (uniforms.model * vec4<f32>(model.normal, 0.0)).xyz;
+    let corner = index % 3u;
+    if (corner == 0u) {
+        out.barycentric = vec3<f32>(1.0, 0.0, 0.0);
+    } elseif (corner == 1u) {
+        out.barycentric = vec3<f32>(0.0, 1.0, 0.0);
+    } else {
+        out.barycentric = vec3<f32>(0.0, 0.0, 1.0);
+    }
+    out.clip_position = uniforms.mvp * vec4<f32>(model.position, 1.0);
+    return out;
+}
+
+// Every view discards the fragments the id pass does, see AlphaMode::cutoff
+
+[[stage(fragment)]]
+fn normals(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    if (textureSample(t_diffuse, s_diffuse, in.tex_coords).a < uniforms.alpha_cutoff) {
+        discard;
+    }
+    let n = normalize(in.world_normal);
+    return vec4<f32>(n * 0.5 + vec3<f32>(0.5, 0.5, 0.5), 1.0);
+}
+
+[[stage(fragment)]]
+fn tex_coords(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    if (textureSample(t_diffuse, s_diffuse, in.tex_coords).a < uniforms.alpha_cutoff) {
+        discard;
+    }
+    return vec4<f32>(fract(in.tex_coords), 0.0, 1.0);
+}
+
+// Black at the near plane to white at the far plane
+[[stage(fragment)]]
+fn depth(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    if (textureSample(t_diffuse, s_diffuse, in.tex_coords).a < uniforms.alpha_cutoff) {
+        discard;
+    }
+    // Undoes OPENGL_TO_WGPU_MATRIX and the perspective projection
+    let z = in.clip_position.z * 2.0 - 1.0;
+    let view_depth = 2.0 * params.near * params.far / (params.far + params.near - z * (params.far - params.near));
+    let d = (view_depth - params.near) / (params.far - params.near);
+    return vec4<f32>(d, d, d, 1.0);
+}
+
+// Added up in the red channel, see shaders/post/heat_map.wgsl
+[[stage(fragment)]]
+fn overdraw(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    if (textureSample(t_diffuse, s_diffuse, in.tex_coords).a < uniforms.alpha_cutoff) {
+        discard;
+    }
+    return vec4<f32>(1.0, 0.0, 0.0, 1.0);
+}
+
+// With PolygonMode::Line only the edges are rasterized
+[[stage(fragment)]]
+fn wireframe(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(1.0, 1.0, 1.0, 1.0);
+}
+
+// Without it, pixels close to an edge are those where one of the barycentric coordinates is close to 0
+[[stage(fragment)]]
+fn wireframe_barycentric(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let width = fwidth(in.barycentric) * 1.5;
+    let distance_to_edge = min(min(in.barycentric.x / width.x, in.barycentric.y / width.y), in.barycentric.z / width.z);
+    if (distance_to_edge > 1.0) {
+        discard;
+    }
+    let line = 1.0 - distance_to_edge;
+    return vec4<f32>(line, line, line, 1.0);
+}
@@ -1,25 +1,30 @@
 struct VertexInput {
     [[location(0)]] position: vec3<f32>;
     [[location(1)]] tex_coords: vec2<f32>;
+    [[location(2)]] normal: vec3<f32>;
+    [[location(3)]] tangent: vec4<f32>;
 };
 
 struct VertexOutput {
     [[builtin(position)]] clip_position: vec4<f32>;
     [[location(0)]] tex_coords: vec2<f32>;
+    [[location(1)]] world_position: vec3<f32>;
+    [[location(2)]] world_normal: vec3<f32>;
+    // w is the handedness of the tangent frame
+    [[location(3)]] world_tangent: vec4<f32>;
 };
 
-[[block]] // 1.
-struct Uniforms {
-    mvp: mat4x4<f32>;
-};
-[[group(1), binding(0)]] // 2.
-var<uniform> uniforms: Uniforms;
+#include "include/object_uniforms.wgsl"
 
 
 [[stage(vertex)]]
 fn main(model: VertexInput) -> VertexOutput {
     var out: VertexOutput;
     out.tex_coords = model.tex_coords;
+    out.world_position = (uniforms.model * vec4<f32>(model.position, 1.0)).xyz;
+    // Transforms only have a uniform scale, so the model matrix keeps normals perpendicular
+    out.world_normal = (uniforms.model * vec4<f32>(model.normal, 0.0)).xyz;
+    out.world_tangent = vec4<f32>((uniforms.model * vec4<f32>(model.tangent.xyz, 0.0)).xyz, model.tangent.w);
     out.clip_position = uniforms.mvp * vec4<f32>(model.position, 1.0);
     return out;
 }
@@ -29,9 +34,79 @@ fn main(model: VertexInput) -> VertexOutput {
 var t_diffuse: texture_2d<f32>;
 [[group(0), binding(1)]]
 var s_diffuse: sampler;
+[[group(0), binding(2)]]
+var t_normal: texture_2d<f32>;
+[[group(0), binding(3)]]
+var s_normal: sampler;
+
+// Image based lighting, see environment.rs
+[[group(2), binding(0)]]
+var t_irradiance: texture_cube<f32>;
+[[group(2), binding(1)]]
+var t_prefiltered: texture_cube<f32>;
+[[group(2), binding(2)]]
+var t_brdf_lut: texture_2d<f32>;
+[[group(2), binding(3)]]
+var s_environment: sampler;
+
+// environment::PREFILTERED_MIP_LEVELS - 1
+let MAX_REFLECTION_LOD: f32 = 4.0;
+
+fn fresnel_schlick_roughness(cos_theta: f32, f0: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let r = vec3<f32>(1.0 - roughness, 1.0 - roughness, 1.0 - roughness);
+    return f0 + (max(r, f0) - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+// Surface normal perturbed by the normal map, using the TBN matrix of the interpolated tangent frame
+fn surface_normal(in: VertexOutput) -> vec3<f32> {
+    let n = normalize(in.world_normal);
+    // Interpolation skews the frame, so the tangent is made perpendicular again
+    let t = normalize(in.world_tangent.xyz - n * dot(n, in.world_tangent.xyz));
+    // Along increasing v, which points down the texture
+    let b = cross(n, t) * in.world_tangent.w;
+
+    let sampled = textureSample(t_normal, s_normal, in.tex_coords).xyz * 2.0 - vec3<f32>(1.0, 1.0, 1.0);
+    // Green points up the texture, the opposite of b
+    let tangent_space = vec3<f32>(sampled.x * uniforms.normal_scale, -sampled.y * uniforms.normal_scale, sampled.z);
+    return normalize(t * tangent_space.x + b * tangent_space.y + n * tangent_space.z);
+}
+
+// Ambient light from the environment, scaled by the scene light
+fn shade(in: VertexOutput, albedo: vec3<f32>) -> vec3<f32> {
+    var n: vec3<f32> = surface_normal(in);
+    let v = normalize(uniforms.camera_position.xyz - in.world_position);
+    // Lights the back of two sided surfaces like the front
+    if (dot(n, v) < 0.0) {
+        n = -n;
+    }
+    let n_dot_v = max(dot(n, v), 0.0);
+    let r = reflect(-v, n);
+
+    let f0 = mix(vec3<f32>(0.04, 0.04, 0.04), albedo, vec3<f32>(uniforms.metallic, uniforms.metallic, uniforms.metallic));
+    let f = fresnel_schlick_roughness(n_dot_v, f0, uniforms.roughness);
+    let kd = (vec3<f32>(1.0, 1.0, 1.0) - f) * (1.0 - uniforms.metallic);
+
+    let irradiance = textureSample(t_irradiance, s_environment, n).rgb;
+    let diffuse = irradiance * albedo;
+    let prefiltered = textureSampleLevel(t_prefiltered, s_environment, r, uniforms.roughness * MAX_REFLECTION_LOD).rgb;
+    let brdf = textureSample(t_brdf_lut, s_environment, vec2<f32>(n_dot_v, uniforms.roughness)).rg;
+    let specular = prefiltered * (f * brdf.x + brdf.y);
+
+    return (kd * diffuse + specular) * uniforms.light.rgb;
+}
 
 [[stage(fragment)]]
 fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
-     return textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    let color = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    return vec4<f32>(shade(in, color.rgb), color.a);
 }
 
+// Alpha tested variant, for cutouts like foliage
+[[stage(fragment)]]
+fn main_mask(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let color = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    if (color.a < uniforms.alpha_cutoff) {
+        discard;
+    }
+    return vec4<f32>(shade(in, color.rgb), 1.0);
+}
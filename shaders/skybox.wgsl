@@ -0,0 +1,22 @@
+// Drawn on the far plane, so anything drawn in the scene is in front of it
+#include "include/fullscreen.wgsl"
+
+[[block]]
+struct Uniforms {
+    // Inverse of the view projection without the camera's translation
+    inv_view_projection: mat4x4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> uniforms: Uniforms;
+[[group(0), binding(1)]]
+var t_sky: texture_cube<f32>;
+[[group(0), binding(2)]]
+var s_sky: sampler;
+
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let ndc = vec2<f32>(in.tex_coords.x * 2.0 - 1.0, 1.0 - in.tex_coords.y * 2.0);
+    let world = uniforms.inv_view_projection * vec4<f32>(ndc, 1.0, 1.0);
+    let direction = normalize(world.xyz / world.w);
+    return textureSample(t_sky, s_sky, direction);
+}
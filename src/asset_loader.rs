@@ -0,0 +1,108 @@
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use anyhow::*;
+
+use crate::mesh::MeshData;
+
+pub enum LoadRequest {
+    Texture { id: u64, path: PathBuf },
+    Mesh { id: u64, path: PathBuf },
+}
+
+pub enum LoadResult {
+    Texture { id: u64, path: PathBuf, image: Result<image::DynamicImage> },
+    Mesh { id: u64, path: PathBuf, data: Result<MeshData> },
+}
+
+// Pool of worker threads doing the CPU heavy part of asset loading (decoding and parsing).
+// Uploading the results to the GPU is left to whoever polls the results on the render thread.
+pub struct AssetLoader {
+    requests: Option<Sender<LoadRequest>>,
+    results: Receiver<LoadResult>,
+    workers: Vec<thread::JoinHandle<()>>,
+    pending: usize,
+}
+
+impl AssetLoader {
+    pub fn new(num_workers: usize) -> Self {
+        let (request_sender, request_receiver) = channel::<LoadRequest>();
+        let (result_sender, results) = channel();
+        let request_receiver = Arc::new(Mutex::new(request_receiver));
+
+        let workers = (0..num_workers.max(1))
+            .map(|i| {
+                let requests = request_receiver.clone();
+                let results = result_sender.clone();
+                thread::Builder::new()
+                    .name(format!("asset loader {}", i))
+                    .spawn(move || Self::work(requests, results))
+                    .expect("Failed to spawn asset loader thread")
+            })
+            .collect();
+
+        Self {
+            requests: Some(request_sender),
+            results,
+            workers,
+            pending: 0,
+        }
+    }
+
+    fn work(requests: Arc<Mutex<Receiver<LoadRequest>>>, results: Sender<LoadResult>) {
+        loop {
+            // The lock is released as soon as a request has been taken
+            let request = match requests.lock().unwrap().recv() {
+                Ok(request) => request,
+                // The loader has been dropped
+                Err(_) => return,
+            };
+
+            let result = match request {
+                LoadRequest::Texture { id, path } => {
+                    let image = image::open(&path)
+                        .with_context(|| format!("Failed to load texture {}", path.display()));
+                    LoadResult::Texture { id, path, image }
+                }
+                LoadRequest::Mesh { id, path } => {
+                    let data = MeshData::from_obj(&path)
+                        .with_context(|| format!("Failed to load mesh {}", path.display()));
+                    LoadResult::Mesh { id, path, data }
+                }
+            };
+
+            if results.send(result).is_err() {
+                return;
+            }
+        }
+    }
+
+    pub fn request(&mut self, request: LoadRequest) {
+        self.pending += 1;
+        self.requests.as_ref().unwrap().send(request)
+            .expect("Asset loader threads have stopped");
+    }
+
+    // Results that have finished since the last call, never blocks
+    pub fn finished(&mut self) -> Vec<LoadResult> {
+        let results: Vec<LoadResult> = self.results.try_iter().collect();
+        self.pending -= results.len();
+        results
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+}
+
+impl Drop for AssetLoader {
+    fn drop(&mut self) {
+        // Closing the request channel makes the workers exit once their current request is done
+        self.requests.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
@@ -0,0 +1,432 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+
+use anyhow::*;
+
+use crate::asset_loader::{AssetLoader, LoadRequest, LoadResult};
+use crate::mesh::{Mesh, MeshData};
+use crate::texture::Texture;
+
+// Ids are unique across all asset types
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+// A typed, reference counted reference to an asset owned by the AssetManager.
+// The asset stays alive for as long as at least one handle to it exists.
+pub struct Handle<T> {
+    id: u64,
+    token: Arc<()>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            token: self.token.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Handle<{}>({})", std::any::type_name::<T>(), self.id)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AssetStatus {
+    // A placeholder is used until loading finishes
+    Loading,
+    Loaded,
+    // A fallback is used in place of the asset
+    Failed,
+}
+
+struct Entry<T> {
+    asset: T,
+    status: AssetStatus,
+    path: Option<PathBuf>,
+    token: Weak<()>,
+}
+
+// Storage for a single asset type, deduplicating by path
+struct AssetStorage<T> {
+    entries: HashMap<u64, Entry<T>>,
+    by_path: HashMap<PathBuf, u64>,
+}
+
+impl<T> AssetStorage<T> {
+    fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+            by_path: HashMap::new(),
+        }
+    }
+
+    fn find(&self, path: &Path) -> Option<Handle<T>> {
+        let id = *self.by_path.get(path)?;
+        let token = self.entries.get(&id)?.token.upgrade()?;
+        Some(Handle { id, token, _marker: PhantomData })
+    }
+
+    fn insert(&mut self, asset: T, status: AssetStatus, path: Option<PathBuf>) -> Handle<T> {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+
+        let token = Arc::new(());
+        if let Some(path) = &path {
+            self.by_path.insert(path.clone(), id);
+        }
+        self.entries.insert(id, Entry {
+            asset,
+            status,
+            path,
+            token: Arc::downgrade(&token),
+        });
+        Handle { id, token, _marker: PhantomData }
+    }
+
+    fn get(&self, handle: &Handle<T>) -> &T {
+        &self.entries[&handle.id].asset
+    }
+
+    fn status(&self, handle: &Handle<T>) -> AssetStatus {
+        self.entries[&handle.id].status
+    }
+
+    // Swaps in a loaded asset, returns false if the asset has been freed in the meantime
+    fn replace(&mut self, id: u64, asset: T, status: AssetStatus) -> bool {
+        match self.entries.get_mut(&id) {
+            Some(entry) => {
+                entry.asset = asset;
+                entry.status = status;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Drops every asset no handle refers to anymore, returning how many were freed
+    fn free_unused(&mut self) -> usize {
+        let unused: Vec<u64> = self.entries.iter()
+            .filter(|(_, entry)| entry.token.strong_count() == 0)
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in &unused {
+            let entry = self.entries.remove(id).unwrap();
+            // The path may already point to a reload of the same file
+            if let Some(path) = entry.path {
+                if self.by_path.get(&path) == Some(id) {
+                    self.by_path.remove(&path);
+                }
+            }
+        }
+        unused.len()
+    }
+}
+
+// Source of a WGSL file with every `#include "path"` line replaced by that file, relative to the
+// including one. Files are only included once, so shared declarations can be included by several.
+fn preprocess_shader(path: &Path, included: &mut HashSet<PathBuf>) -> Result<String> {
+    let path = path.canonicalize()
+        .with_context(|| format!("Failed to load shader {}", path.display()))?;
+    if !included.insert(path.clone()) {
+        return Ok(String::new());
+    }
+    let source = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to load shader {}", path.display()))?;
+
+    let mut output = String::with_capacity(source.len());
+    for line in source.lines() {
+        match line.trim().strip_prefix("#include") {
+            Some(include) => {
+                let include = include.trim().trim_matches('"');
+                let include_path = path.parent().unwrap_or_else(|| Path::new("")).join(include);
+                output.push_str(&preprocess_shader(&include_path, included)
+                    .with_context(|| format!("Included from {}", path.display()))?);
+            }
+            None => {
+                output.push_str(line);
+                output.push('\n');
+            }
+        }
+    }
+    Ok(output)
+}
+
+// Ids of assets which finished loading in the background since the last poll
+#[derive(Debug, Default)]
+pub struct LoadedAssets {
+    pub textures: Vec<u64>,
+    pub meshes: Vec<u64>,
+}
+
+impl LoadedAssets {
+    pub fn contains_texture(&self, handle: &Handle<Texture>) -> bool {
+        self.textures.contains(&handle.id)
+    }
+
+    pub fn contains_mesh(&self, handle: &Handle<Mesh>) -> bool {
+        self.meshes.contains(&handle.id)
+    }
+}
+
+// Next to the executable when res and shaders have been installed there, otherwise the crate it
+// was built from, so the binary finds its assets from any working directory
+pub fn default_root() -> PathBuf {
+    std::env::current_exe().ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+        .filter(|dir| dir.join("shaders").is_dir())
+        .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")))
+}
+
+pub struct AssetManager {
+    root: PathBuf,
+    textures: AssetStorage<Texture>,
+    meshes: AssetStorage<Mesh>,
+    shaders: AssetStorage<wgpu::ShaderModule>,
+    loader: AssetLoader,
+}
+
+impl AssetManager {
+    // All asset paths are resolved relative to `root`
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        let num_workers = std::thread::available_parallelism()
+            .map(|n| n.get().saturating_sub(1))
+            .unwrap_or(1);
+
+        Self {
+            root: root.into(),
+            textures: AssetStorage::new(),
+            meshes: AssetStorage::new(),
+            shaders: AssetStorage::new(),
+            loader: AssetLoader::new(num_workers),
+        }
+    }
+
+    fn resolve(&self, path: &Path) -> PathBuf {
+        let path = self.root.join(path);
+        path.canonicalize().unwrap_or(path)
+    }
+
+    // Never fails, normal maps that can't be loaded are flat. Decoded without sRGB, unlike the
+    // textures of load_texture_async.
+    pub fn load_normal_map<P: AsRef<Path>>(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: P
+    ) -> Handle<Texture> {
+        let path = self.resolve(path.as_ref());
+        if let Some(handle) = self.textures.find(&path) {
+            return handle;
+        }
+
+        match Texture::normal_map_from_path(device, queue, &path) {
+            Ok(texture) => self.textures.insert(texture, AssetStatus::Loaded, Some(path)),
+            Err(e) => {
+                log::warn!("{:?}, using a flat normal map", e);
+                self.textures.insert(Texture::flat_normal_map(device, queue), AssetStatus::Failed, Some(path))
+            }
+        }
+    }
+
+    // Returns immediately with a handle to a grey placeholder, which is replaced once the image
+    // has been decoded and `poll` is called
+    pub fn load_texture_async<P: AsRef<Path>>(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: P
+    ) -> Handle<Texture> {
+        let path = self.resolve(path.as_ref());
+        if let Some(handle) = self.textures.find(&path) {
+            return handle;
+        }
+
+        let placeholder = Texture::solid_color(device, queue, [128, 128, 128, 255], "loading texture");
+        let handle = self.textures.insert(placeholder, AssetStatus::Loading, Some(path.clone()));
+        self.loader.request(LoadRequest::Texture { id: handle.id, path });
+        handle
+    }
+
+    // Returns immediately with a handle to a placeholder cube, which is replaced once the file
+    // has been parsed and `poll` is called
+    pub fn load_mesh_async<P: AsRef<Path>>(
+        &mut self,
+        device: &wgpu::Device,
+        path: P
+    ) -> Handle<Mesh> {
+        let path = self.resolve(path.as_ref());
+        if let Some(handle) = self.meshes.find(&path) {
+            return handle;
+        }
+
+        let placeholder = Mesh::from_data(device, &MeshData::cube(), "loading mesh");
+        let handle = self.meshes.insert(placeholder, AssetStatus::Loading, Some(path.clone()));
+        self.loader.request(LoadRequest::Mesh { id: handle.id, path });
+        handle
+    }
+
+    // Uploads everything the loader threads have finished since the last call.
+    // Must be called from the thread owning the device, typically once per frame.
+    pub fn poll(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> LoadedAssets {
+        let mut loaded = LoadedAssets::default();
+        for result in self.loader.finished() {
+            match result {
+                LoadResult::Texture { id, path, image } => {
+                    let texture = image.and_then(|image| {
+                        Texture::from_image(device, queue, &image, path.to_str())
+                    });
+                    let (texture, status) = match texture {
+                        Ok(texture) => (texture, AssetStatus::Loaded),
+                        Err(e) => {
+                            log::warn!("{:?}, using missing texture", e);
+                            (Texture::checkerboard(device, queue, "missing texture"), AssetStatus::Failed)
+                        }
+                    };
+                    if self.textures.replace(id, texture, status) {
+                        loaded.textures.push(id);
+                    }
+                }
+                LoadResult::Mesh { id, path, data } => {
+                    match data {
+                        Ok(data) => {
+                            let mesh = Mesh::from_data(device, &data, &path.display().to_string());
+                            if self.meshes.replace(id, mesh, AssetStatus::Loaded) {
+                                loaded.meshes.push(id);
+                            }
+                        }
+                        Err(e) => {
+                            log::error!("{:?}", e);
+                            if let Some(entry) = self.meshes.entries.get_mut(&id) {
+                                entry.status = AssetStatus::Failed;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        loaded
+    }
+
+    // Number of assets still being loaded in the background
+    pub fn pending(&self) -> usize {
+        self.loader.pending()
+    }
+
+    pub fn load_shader<P: AsRef<Path>>(
+        &mut self,
+        device: &wgpu::Device,
+        path: P
+    ) -> Result<Handle<wgpu::ShaderModule>> {
+        let path = self.resolve(path.as_ref());
+        if let Some(handle) = self.shaders.find(&path) {
+            return Ok(handle);
+        }
+
+        let source = preprocess_shader(&path, &mut HashSet::new())?;
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: path.to_str(),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+            flags: wgpu::ShaderFlags::all(),
+        });
+        Ok(self.shaders.insert(shader, AssetStatus::Loaded, Some(path)))
+    }
+
+    // Registers assets created in code, these are never deduplicated
+    pub fn add_texture(&mut self, texture: Texture) -> Handle<Texture> {
+        self.textures.insert(texture, AssetStatus::Loaded, None)
+    }
+
+    pub fn add_mesh(&mut self, mesh: Mesh) -> Handle<Mesh> {
+        self.meshes.insert(mesh, AssetStatus::Loaded, None)
+    }
+
+    pub fn texture(&self, handle: &Handle<Texture>) -> &Texture {
+        self.textures.get(handle)
+    }
+
+    pub fn mesh(&self, handle: &Handle<Mesh>) -> &Mesh {
+        self.meshes.get(handle)
+    }
+
+    pub fn shader(&self, handle: &Handle<wgpu::ShaderModule>) -> &wgpu::ShaderModule {
+        self.shaders.get(handle)
+    }
+
+    pub fn texture_status(&self, handle: &Handle<Texture>) -> AssetStatus {
+        self.textures.status(handle)
+    }
+
+    pub fn mesh_status(&self, handle: &Handle<Mesh>) -> AssetStatus {
+        self.meshes.status(handle)
+    }
+
+    // Dropping the assets releases their GPU resources
+    pub fn free_unused(&mut self) -> usize {
+        self.textures.free_unused() + self.meshes.free_unused() + self.shaders.free_unused()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shader_includes_are_expanded_once() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let source = preprocess_shader(&dir.join("shaders/post/vignette.wgsl"), &mut HashSet::new()).unwrap();
+        assert!(!source.contains("#include"));
+        assert_eq!(source.matches("struct VertexOutput").count(), 1);
+        assert_eq!(source.matches("fn main(").count(), 2);
+
+        let mut included = HashSet::new();
+        preprocess_shader(&dir.join("shaders/blit.wgsl"), &mut included).unwrap();
+        assert!(preprocess_shader(&dir.join("shaders/include/fullscreen.wgsl"), &mut included).unwrap().is_empty());
+        assert!(preprocess_shader(&dir.join("shaders/does_not_exist.wgsl"), &mut included).is_err());
+    }
+
+    #[test]
+    fn freeing_a_dropped_asset_keeps_the_reloaded_path() {
+        let mut storage: AssetStorage<u32> = AssetStorage::new();
+        let path = PathBuf::from("textures/bricks.png");
+        drop(storage.insert(1, AssetStatus::Loaded, Some(path.clone())));
+        assert!(storage.find(&path).is_none());
+
+        let reloaded = storage.insert(2, AssetStatus::Loaded, Some(path.clone()));
+        assert_eq!(storage.free_unused(), 1);
+        assert_eq!(storage.find(&path), Some(reloaded.clone()));
+        assert_eq!(*storage.get(&reloaded), 2);
+
+        drop(reloaded);
+        assert_eq!(storage.free_unused(), 1);
+        assert!(storage.by_path.is_empty());
+    }
+}
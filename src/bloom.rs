@@ -0,0 +1,314 @@
+use std::sync::Arc;
+
+use anyhow::*;
+
+use crate::asset_manager::{AssetManager, Handle};
+use crate::pipeline::{PipelineBuilder, PipelineCache};
+use crate::texture::Texture;
+
+// Smallest mip level is at least this many pixels on its shorter side
+const MIN_MIP_SIZE: u32 = 8;
+const MAX_MIP_LEVELS: u32 = 7;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct BloomParams {
+    threshold: f32,
+    knee: f32,
+    intensity: f32,
+    _padding: f32,
+}
+
+const ADDITIVE: wgpu::BlendState = wgpu::BlendState {
+    color: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    },
+    // Leaves the target's alpha alone
+    alpha: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::Zero,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    },
+};
+
+// Makes bright parts of the HDR scene glow. The parts above the threshold are blurred by
+// downsampling them through a chain of mip levels at half the scene resolution and back up
+// again, and the result is added onto the scene before it's tonemapped.
+pub struct Bloom {
+    pub enabled: bool,
+    // Colors brighter than this start to bloom
+    pub threshold: f32,
+    // Fraction of the threshold below it over which bloom fades in, 0 is a hard cutoff
+    pub soft_knee: f32,
+    pub intensity: f32,
+    // Half the scene resolution, read and written through mip_views
+    mips: wgpu::Texture,
+    mip_views: Vec<wgpu::TextureView>,
+    sampler: wgpu::Sampler,
+    params_buffer: wgpu::Buffer,
+    layout: wgpu::BindGroupLayout,
+    scene_bind_group: wgpu::BindGroup,
+    // One per mip level
+    mip_bind_groups: Vec<wgpu::BindGroup>,
+    prefilter_pipeline: Arc<wgpu::RenderPipeline>,
+    downsample_pipeline: Arc<wgpu::RenderPipeline>,
+    upsample_pipeline: Arc<wgpu::RenderPipeline>,
+    composite_pipeline: Arc<wgpu::RenderPipeline>,
+}
+
+impl Bloom {
+    pub fn new(
+        device: &wgpu::Device,
+        assets: &mut AssetManager,
+        cache: &mut PipelineCache,
+        sc_desc: &wgpu::SwapChainDescriptor,
+        scene: &Texture
+    ) -> Result<Self> {
+        let shader: Handle<wgpu::ShaderModule> = assets.load_shader(device, "shaders/post/bloom.wgsl")?;
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                        filtering: true,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("bloom_bind_group_layout"),
+        });
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Bloom Params Buffer"),
+            size: std::mem::size_of::<BloomParams>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let pipeline_layout = Arc::new(
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Bloom Pipeline Layout"),
+                bind_group_layouts: &[&layout],
+                push_constant_ranges: &[],
+            })
+        );
+        let pipeline = |name: &str, entry_point: &str| {
+            PipelineBuilder::new(name, &pipeline_layout, &shader, Texture::HDR_FORMAT)
+                .fragment_entry_point(Some(entry_point))
+                .cull_mode(None)
+        };
+        let prefilter_pipeline = pipeline("Bloom Prefilter Pipeline", "prefilter").build(device, assets, cache);
+        let downsample_pipeline = pipeline("Bloom Downsample Pipeline", "downsample").build(device, assets, cache);
+        let upsample_pipeline = pipeline("Bloom Upsample Pipeline", "upsample")
+            .blend(Some(ADDITIVE))
+            .build(device, assets, cache);
+        let composite_pipeline = pipeline("Bloom Composite Pipeline", "composite")
+            .blend(Some(ADDITIVE))
+            .build(device, assets, cache);
+
+        let (mips, mip_views) = Self::create_mips(device, sc_desc);
+        let scene_bind_group = Self::create_bind_group(device, &layout, &scene.view, &sampler, &params_buffer);
+        let mip_bind_groups = mip_views.iter()
+            .map(|view| Self::create_bind_group(device, &layout, view, &sampler, &params_buffer))
+            .collect();
+
+        Ok(Self {
+            enabled: true,
+            threshold: 1.0,
+            soft_knee: 0.5,
+            intensity: 0.3,
+            mips,
+            mip_views,
+            sampler,
+            params_buffer,
+            layout,
+            scene_bind_group,
+            mip_bind_groups,
+            prefilter_pipeline,
+            downsample_pipeline,
+            upsample_pipeline,
+            composite_pipeline,
+        })
+    }
+
+    fn create_mips(device: &wgpu::Device, sc_desc: &wgpu::SwapChainDescriptor) -> (wgpu::Texture, Vec<wgpu::TextureView>) {
+        let width = (sc_desc.width / 2).max(1);
+        let height = (sc_desc.height / 2).max(1);
+        let mip_level_count = mip_level_count(width, height);
+        let mips = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("bloom_mips"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Texture::HDR_FORMAT,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+        });
+        // Every pass reads one level and writes another, so each level gets its own view
+        let views = (0..mip_level_count)
+            .map(|level| mips.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("bloom_mip_view"),
+                base_mip_level: level,
+                mip_level_count: std::num::NonZeroU32::new(1),
+                ..Default::default()
+            }))
+            .collect();
+        (mips, views)
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        view: &wgpu::TextureView,
+        sampler: &wgpu::Sampler,
+        params_buffer: &wgpu::Buffer
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("bloom_bind_group"),
+        })
+    }
+
+    pub fn mip_level_count(&self) -> usize {
+        self.mip_views.len()
+    }
+
+    // The scene texture is recreated on resize too, so it has to be passed in again
+    pub fn resize(&mut self, device: &wgpu::Device, sc_desc: &wgpu::SwapChainDescriptor, scene: &Texture) {
+        let (mips, mip_views) = Self::create_mips(device, sc_desc);
+        self.mips = mips;
+        self.mip_views = mip_views;
+        self.scene_bind_group = Self::create_bind_group(device, &self.layout, &scene.view, &self.sampler, &self.params_buffer);
+        self.mip_bind_groups = self.mip_views.iter()
+            .map(|view| Self::create_bind_group(device, &self.layout, view, &self.sampler, &self.params_buffer))
+            .collect();
+    }
+
+    // Adds the bloom onto the scene, which has to be the texture passed to new or resize
+    pub fn run(&self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, scene: &wgpu::TextureView) {
+        let params = BloomParams {
+            threshold: self.threshold,
+            knee: self.threshold * self.soft_knee,
+            intensity: self.intensity,
+            _padding: 0.0,
+        };
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
+
+        let last = self.mip_views.len() - 1;
+        Self::pass(encoder, "Bloom Prefilter", &self.mip_views[0], true, &self.prefilter_pipeline, &self.scene_bind_group);
+        for level in 1..=last {
+            Self::pass(encoder, "Bloom Downsample", &self.mip_views[level], true, &self.downsample_pipeline, &self.mip_bind_groups[level - 1]);
+        }
+        for level in (0..last).rev() {
+            Self::pass(encoder, "Bloom Upsample", &self.mip_views[level], false, &self.upsample_pipeline, &self.mip_bind_groups[level + 1]);
+        }
+        Self::pass(encoder, "Bloom Composite", scene, false, &self.composite_pipeline, &self.mip_bind_groups[0]);
+    }
+
+    fn pass(
+        encoder: &mut wgpu::CommandEncoder,
+        label: &str,
+        target: &wgpu::TextureView,
+        clear: bool,
+        pipeline: &wgpu::RenderPipeline,
+        source: &wgpu::BindGroup
+    ) {
+        let load = if clear {
+            wgpu::LoadOp::Clear(wgpu::Color::BLACK)
+        } else {
+            wgpu::LoadOp::Load
+        };
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(label),
+            color_attachments: &[
+                wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load,
+                        store: true,
+                    }
+                }
+            ],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, source, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+// Halves the size until the next level would be smaller than MIN_MIP_SIZE
+fn mip_level_count(width: u32, height: u32) -> u32 {
+    let mut size = width.min(height);
+    let mut count = 1;
+    while count < MAX_MIP_LEVELS && size / 2 >= MIN_MIP_SIZE {
+        size /= 2;
+        count += 1;
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mip_chain_stops_at_the_minimum_size() {
+        assert_eq!(mip_level_count(1, 1), 1);
+        assert_eq!(mip_level_count(16, 100), 2);
+        assert_eq!(mip_level_count(960, 540), 7);
+        assert_eq!(mip_level_count(100, 31), 2);
+    }
+}
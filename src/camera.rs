@@ -1,23 +1,75 @@
-use cgmath::{Matrix4, SquareMatrix};
+use std::fmt;
+
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, SquareMatrix, Vector3, Vector4};
 
 use crate::transformation_matrix::TransformationMatrix;
 
+#[derive(Clone)]
 pub struct Camera {
-    pub model_transform: TransformationMatrix,
     pub camera_transform: TransformationMatrix,
     pub projection: Matrix4<f32>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraError {
+    // The camera transform can't be inverted into a view matrix, e.g. because it has a scale of 0
+    SingularTransform,
+}
 
+impl fmt::Display for CameraError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CameraError::SingularTransform => write!(f, "The camera transform is not invertible"),
+        }
+    }
+}
+
+impl std::error::Error for CameraError {}
 
 impl Camera {
-    pub fn build_model_view_projection_matrix(&self) -> [[f32; 4]; 4] {
-        let view = self.camera_transform.compute_transformation_matrix().invert().unwrap();
-        dbg!(self.model_transform.compute_transformation_matrix());
-        let model_view_proj_matrix = OPENGL_TO_WGPU_MATRIX * self.projection * view * self.model_transform.compute_transformation_matrix();
-        let model_view_proj_matrix: [[f32; 4]; 4] = *model_view_proj_matrix.as_ref();
-        return model_view_proj_matrix
+    // Multiply with an object's transformation matrix to get its model view projection matrix
+    pub fn build_view_projection_matrix(&self) -> Result<Matrix4<f32>, CameraError> {
+        let view = self.camera_transform.compute_transformation_matrix().invert()
+            .ok_or(CameraError::SingularTransform)?;
+        Ok(OPENGL_TO_WGPU_MATRIX * self.projection * view)
+    }
+
+    // View projection with the camera's translation removed, so the skybox stays infinitely far away
+    pub fn build_skybox_view_projection_matrix(&self) -> Result<Matrix4<f32>, CameraError> {
+        let mut view = self.camera_transform.compute_transformation_matrix().invert()
+            .ok_or(CameraError::SingularTransform)?;
+        view.w = Vector4::new(0.0, 0.0, 0.0, 1.0);
+        Ok(OPENGL_TO_WGPU_MATRIX * self.projection * view)
+    }
+
+    // Camera between self and next, used to render in between two fixed updates
+    pub fn interpolate(&self, next: &Camera, alpha: f32) -> Camera {
+        Camera {
+            camera_transform: self.camera_transform.lerp(&next.camera_transform, alpha),
+            projection: next.projection,
+        }
+    }
+
+    // Position of the camera in world space
+    pub fn position(&self) -> Vector3<f32> {
+        self.camera_transform.compute_transformation_matrix().w.truncate()
+    }
+
+    // Near and far plane of the projection, which has to be a cgmath::perspective one
+    pub fn depth_range(&self) -> (f32, f32) {
+        // z.z is (far + near) / (near - far) and w.z is 2 * far * near / (near - far)
+        let (a, b) = (self.projection.z.z, self.projection.w.z);
+        (b / (a - 1.0), b / (a + 1.0))
+    }
 
+    // Fraction of the screen height a sphere covers, 1 once the camera is inside it
+    pub fn screen_size(&self, center: Point3<f32>, radius: f32) -> f32 {
+        let distance2 = (center.to_vec() - self.position()).magnitude2();
+        if distance2 <= radius * radius {
+            return 1.0;
+        }
+        // projection.y.y is the cotangent of half the vertical field of view
+        (radius * self.projection.y.y / (distance2 - radius * radius).sqrt()).min(1.0)
     }
 }
 
@@ -27,4 +79,53 @@ pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     0.0, 1.0, 0.0, 0.0,
     0.0, 0.0, 0.5, 0.0,
     0.0, 0.0, 0.5, 1.0,
-);
\ No newline at end of file
+);
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Deg, Vector3};
+
+    use super::*;
+
+    fn camera(camera_transform: TransformationMatrix) -> Camera {
+        Camera {
+            camera_transform,
+            projection: cgmath::perspective(Deg(90.0), 1.0, 0.1, 10.0),
+        }
+    }
+
+    #[test]
+    fn invertible_camera_transform_builds_matrices() {
+        let camera = camera(TransformationMatrix::new(Vector3::new(0.0, 0.0, 2.0), Deg(10.0), Deg(20.0), Deg(30.0)));
+        assert!(camera.build_view_projection_matrix().is_ok());
+        assert!(camera.build_skybox_view_projection_matrix().is_ok());
+    }
+
+    #[test]
+    fn screen_size_shrinks_with_distance() {
+        let camera = camera(TransformationMatrix::new(Vector3::new(0.0, 0.0, 0.0), Deg(0.0), Deg(0.0), Deg(0.0)));
+        // With a 90 degree field of view a sphere seen at a tangent angle of 45 degrees fills the screen
+        let radius = std::f32::consts::FRAC_1_SQRT_2;
+        assert!((camera.screen_size(Point3::new(0.0, 0.0, -1.0), radius) - 1.0).abs() < 1e-5);
+        let far = camera.screen_size(Point3::new(0.0, 0.0, -10.0), 0.5);
+        assert!((far - 0.5 / (100.0f32 - 0.25).sqrt()).abs() < 1e-5);
+        assert_eq!(camera.screen_size(Point3::new(0.0, 0.0, -0.1), 0.5), 1.0);
+    }
+
+    #[test]
+    fn depth_range_comes_from_the_projection() {
+        let camera = camera(TransformationMatrix::new(Vector3::new(0.0, 0.0, 0.0), Deg(0.0), Deg(0.0), Deg(0.0)));
+        let (near, far) = camera.depth_range();
+        assert!((near - 0.1).abs() < 1e-5);
+        assert!((far - 10.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn zero_scale_camera_transform_is_singular() {
+        let mut transform = TransformationMatrix::new(Vector3::new(0.0, 0.0, 2.0), Deg(0.0), Deg(0.0), Deg(0.0));
+        transform.scale = 0.0;
+        let camera = camera(transform);
+        assert_eq!(camera.build_view_projection_matrix().unwrap_err(), CameraError::SingularTransform);
+        assert_eq!(camera.build_skybox_view_projection_matrix().unwrap_err(), CameraError::SingularTransform);
+    }
+}
@@ -0,0 +1,272 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Sender};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::*;
+use futures::executor::block_on;
+
+use crate::texture::Texture;
+
+struct Recording {
+    dir: PathBuf,
+    next_frame: u32,
+}
+
+// A frame copied into a buffer, waiting for the GPU to finish
+struct PendingCopy {
+    buffer: wgpu::Buffer,
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+    bgra: bool,
+    paths: Vec<PathBuf>,
+}
+
+struct SaveRequest {
+    paths: Vec<PathBuf>,
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+}
+
+// Saves rendered frames as PNGs, either single screenshots or every frame while recording.
+// Encoding happens on a background thread so recording doesn't stall rendering more than needed.
+//
+//     if capture.wants_frame() {
+//         capture.copy_frame(&device, &mut encoder, &frame_texture, width, height, format);
+//     }
+//     queue.submit(...);
+//     capture.finish(&device);
+pub struct FrameCapture {
+    screenshot_dir: PathBuf,
+    screenshots: Vec<PathBuf>,
+    recording: Option<Recording>,
+    pending: Option<PendingCopy>,
+    writer: Option<Sender<SaveRequest>>,
+    writer_thread: Option<thread::JoinHandle<()>>,
+}
+
+impl FrameCapture {
+    // Screenshots without an explicit path are saved in screenshot_dir
+    pub fn new<P: Into<PathBuf>>(screenshot_dir: P) -> Self {
+        let (writer, requests) = channel::<SaveRequest>();
+        let writer_thread = thread::Builder::new()
+            .name("frame capture writer".to_string())
+            .spawn(move || {
+                for request in requests {
+                    for path in &request.paths {
+                        if let Err(e) = save_png(path, &request.rgba, request.width, request.height) {
+                            log::error!("{:?}", e);
+                        }
+                    }
+                }
+            })
+            .expect("Failed to spawn frame capture thread");
+
+        Self {
+            screenshot_dir: screenshot_dir.into(),
+            screenshots: Vec::new(),
+            recording: None,
+            pending: None,
+            writer: Some(writer),
+            writer_thread: Some(writer_thread),
+        }
+    }
+
+    // Saves the next frame to a timestamped file in the screenshot directory
+    pub fn screenshot(&mut self) -> PathBuf {
+        let path = self.screenshot_dir.join(format!("screenshot-{}.png", timestamp()));
+        self.screenshot_to(path.clone());
+        path
+    }
+
+    pub fn screenshot_to<P: Into<PathBuf>>(&mut self, path: P) {
+        self.screenshots.push(path.into());
+    }
+
+    // Saves every following frame as dir/frame-00000.png, dir/frame-00001.png, ...
+    pub fn start_recording<P: Into<PathBuf>>(&mut self, dir: P) {
+        self.recording = Some(Recording { dir: dir.into(), next_frame: 0 });
+    }
+
+    // Starts recording into a new timestamped directory next to the screenshots
+    pub fn start_recording_new(&mut self) -> PathBuf {
+        let dir = self.screenshot_dir.join(format!("recording-{}", timestamp()));
+        self.start_recording(dir.clone());
+        dir
+    }
+
+    // Returns the number of frames recorded
+    pub fn stop_recording(&mut self) -> Option<u32> {
+        self.recording.take().map(|recording| recording.next_frame)
+    }
+
+    // Whether the current frame should be passed to copy_frame
+    pub fn wants_frame(&self) -> bool {
+        !self.screenshots.is_empty() || self.recording.is_some()
+    }
+
+    // Records a copy of the texture into a readback buffer, the texture needs COPY_SRC usage
+    pub fn copy_frame(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &Texture,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat
+    ) {
+        let bgra = match format {
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+            _ => {
+                log::warn!("Can't capture frames in {:?}", format);
+                self.screenshots.clear();
+                self.recording = None;
+                return;
+            }
+        };
+
+        let mut paths = std::mem::take(&mut self.screenshots);
+        if let Some(recording) = &mut self.recording {
+            paths.push(recording.dir.join(format!("frame-{:05}.png", recording.next_frame)));
+            recording.next_frame += 1;
+        }
+
+        let padded_bytes_per_row = padded_bytes_per_row(width);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Frame Capture Buffer"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &texture.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row),
+                    rows_per_image: std::num::NonZeroU32::new(height),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        self.pending = Some(PendingCopy { buffer, width, height, padded_bytes_per_row, bgra, paths });
+    }
+
+    // Reads back the frame copied this frame and hands it to the writer thread, call after submitting
+    pub fn finish(&mut self, device: &wgpu::Device) {
+        let pending = match self.pending.take() {
+            Some(pending) => pending,
+            None => return,
+        };
+
+        let slice = pending.buffer.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        if let Err(e) = block_on(mapping) {
+            log::error!("Failed to read back the captured frame: {:?}", e);
+            return;
+        }
+
+        let mut rgba = unpad_rows(&slice.get_mapped_range(), pending.width * 4, pending.padded_bytes_per_row, pending.height);
+        pending.buffer.unmap();
+        if pending.bgra {
+            bgra_to_rgba(&mut rgba);
+        }
+
+        if let Some(writer) = &self.writer {
+            let _ = writer.send(SaveRequest {
+                paths: pending.paths,
+                width: pending.width,
+                height: pending.height,
+                rgba,
+            });
+        }
+    }
+
+    // Waits for every captured frame to be written, no more frames can be captured afterwards
+    pub fn shutdown(&mut self) {
+        self.writer.take();
+        if let Some(thread) = self.writer_thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for FrameCapture {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn timestamp() -> u128 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_millis()).unwrap_or(0)
+}
+
+fn save_png(path: &Path, rgba: &[u8], width: u32, height: u32) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+    }
+    image::save_buffer(path, rgba, width, height, image::ColorType::Rgba8)
+        .with_context(|| format!("Failed to save {}", path.display()))
+}
+
+// Rows copied out of a texture have to start at multiples of COPY_BYTES_PER_ROW_ALIGNMENT
+pub fn padded_bytes_per_row(width: u32) -> u32 {
+    let unpadded = width * 4;
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    unpadded.div_ceil(align) * align
+}
+
+fn unpad_rows(data: &[u8], bytes_per_row: u32, padded_bytes_per_row: u32, height: u32) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity((bytes_per_row * height) as usize);
+    for row in data.chunks(padded_bytes_per_row as usize).take(height as usize) {
+        pixels.extend_from_slice(&row[..bytes_per_row as usize]);
+    }
+    pixels
+}
+
+fn bgra_to_rgba(pixels: &mut [u8]) {
+    for pixel in pixels.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_are_padded_to_the_copy_alignment() {
+        assert_eq!(padded_bytes_per_row(64), 256);
+        assert_eq!(padded_bytes_per_row(65), 512);
+        assert_eq!(padded_bytes_per_row(1), 256);
+    }
+
+    #[test]
+    fn removes_row_padding() {
+        // Two rows of one pixel, each padded to 8 bytes
+        let data = [1, 2, 3, 4, 0, 0, 0, 0, 5, 6, 7, 8, 0, 0, 0, 0];
+        assert_eq!(unpad_rows(&data, 4, 8, 2), vec![1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn swaps_red_and_blue() {
+        let mut pixels = [10, 20, 30, 40, 50, 60, 70, 80];
+        bgra_to_rgba(&mut pixels);
+        assert_eq!(pixels, [30, 20, 10, 40, 70, 60, 50, 80]);
+    }
+}
@@ -0,0 +1,260 @@
+use anyhow::*;
+use futures::executor::block_on;
+use wgpu::util::DeviceExt;
+
+use crate::asset_manager::{AssetManager, Handle};
+
+// What a compute shader binds at one slot of group 0
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ComputeBinding {
+    Uniform,
+    Storage { read_only: bool },
+    // Sampled, filterable float texture
+    Texture { view_dimension: wgpu::TextureViewDimension },
+    Sampler,
+    StorageTexture {
+        access: wgpu::StorageTextureAccess,
+        format: wgpu::TextureFormat,
+        view_dimension: wgpu::TextureViewDimension,
+    },
+}
+
+impl ComputeBinding {
+    fn layout_entry(&self, binding: u32) -> wgpu::BindGroupLayoutEntry {
+        let ty = match *self {
+            ComputeBinding::Uniform => wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            ComputeBinding::Storage { read_only } => wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            ComputeBinding::Texture { view_dimension } => wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            ComputeBinding::Sampler => wgpu::BindingType::Sampler {
+                comparison: false,
+                filtering: true,
+            },
+            ComputeBinding::StorageTexture { access, format, view_dimension } => wgpu::BindingType::StorageTexture {
+                access,
+                format,
+                view_dimension,
+            },
+        };
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStage::COMPUTE,
+            ty,
+            count: None,
+        }
+    }
+}
+
+// Describes a compute pipeline reading and writing the resources of a single bind group.
+// Bindings are numbered in the order they are added and the entry point is always `main`.
+pub struct ComputeBuilder {
+    label: String,
+    shader: Handle<wgpu::ShaderModule>,
+    workgroup_size: [u32; 3],
+    bindings: Vec<ComputeBinding>,
+}
+
+impl ComputeBuilder {
+    pub fn new(label: &str, shader: &Handle<wgpu::ShaderModule>) -> Self {
+        Self {
+            label: label.to_string(),
+            shader: shader.clone(),
+            workgroup_size: [1, 1, 1],
+            bindings: Vec::new(),
+        }
+    }
+
+    // Has to match the shader's workgroup_size attribute, dispatches are rounded up to it
+    pub fn workgroup_size(mut self, workgroup_size: [u32; 3]) -> Self {
+        self.workgroup_size = workgroup_size;
+        self
+    }
+
+    pub fn binding(mut self, binding: ComputeBinding) -> Self {
+        self.bindings.push(binding);
+        self
+    }
+
+    pub fn build(self, device: &wgpu::Device, assets: &AssetManager) -> ComputeKernel {
+        let entries: Vec<wgpu::BindGroupLayoutEntry> = self.bindings.iter()
+            .enumerate()
+            .map(|(binding, kind)| kind.layout_entry(binding as u32))
+            .collect();
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &entries,
+            label: Some(&self.label),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(&self.label),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(&self.label),
+            layout: Some(&layout),
+            module: assets.shader(&self.shader),
+            entry_point: "main",
+        });
+
+        ComputeKernel {
+            label: self.label,
+            pipeline,
+            bind_group_layout,
+            workgroup_size: self.workgroup_size,
+        }
+    }
+}
+
+pub struct ComputeKernel {
+    label: String,
+    pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    workgroup_size: [u32; 3],
+}
+
+impl ComputeKernel {
+    // One resource per binding, in the order they were added to the builder
+    pub fn bind_group(&self, device: &wgpu::Device, resources: &[wgpu::BindingResource]) -> wgpu::BindGroup {
+        let entries: Vec<wgpu::BindGroupEntry> = resources.iter()
+            .enumerate()
+            .map(|(binding, resource)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: resource.clone(),
+            })
+            .collect();
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &entries,
+            label: Some(&self.label),
+        })
+    }
+
+    // Workgroups needed for at least size invocations along each axis
+    pub fn workgroups(&self, size: [u32; 3]) -> [u32; 3] {
+        [
+            size[0].div_ceil(self.workgroup_size[0]),
+            size[1].div_ceil(self.workgroup_size[1]),
+            size[2].div_ceil(self.workgroup_size[2]),
+        ]
+    }
+
+    // Records a pass running size invocations into an encoder, like the frame's in State::render.
+    // Shaders have to skip the invocations past the end when size isn't a multiple of the workgroup size.
+    pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder, bind_group: &wgpu::BindGroup, size: [u32; 3]) {
+        let [x, y, z] = self.workgroups(size);
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some(&self.label),
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, bind_group, &[]);
+        compute_pass.dispatch(x, y, z);
+    }
+
+    // Dispatches on its own, for work outside of a frame
+    pub fn run(&self, device: &wgpu::Device, queue: &wgpu::Queue, bind_group: &wgpu::BindGroup, size: [u32; 3]) {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some(&self.label),
+        });
+        self.dispatch(&mut encoder, bind_group, size);
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+}
+
+// Buffer a kernel can read and write, which can be updated and read back afterwards
+pub fn create_storage_buffer(device: &wgpu::Device, contents: &[u8], label: &str) -> wgpu::Buffer {
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(label),
+        contents,
+        usage: wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_SRC | wgpu::BufferUsage::COPY_DST,
+    })
+}
+
+// Copies the start of a buffer created with COPY_SRC to the CPU. Blocks until everything
+// submitted before has finished, so it's meant for tools and tests rather than every frame.
+pub fn read_buffer(device: &wgpu::Device, queue: &wgpu::Queue, buffer: &wgpu::Buffer, size: wgpu::BufferAddress) -> Result<Vec<u8>> {
+    let readback = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Compute Readback"),
+        size,
+        usage: wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Compute Readback Encoder"),
+    });
+    encoder.copy_buffer_to_buffer(buffer, 0, &readback, 0, size);
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let slice = readback.slice(..);
+    let mapping = slice.map_async(wgpu::MapMode::Read);
+    device.poll(wgpu::Maintain::Wait);
+    block_on(mapping).map_err(|e| anyhow!("Failed to read back a buffer: {:?}", e))?;
+    let data = slice.get_mapped_range().to_vec();
+    readback.unmap();
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[repr(C)]
+    #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+    struct ScaleParams {
+        factor: u32,
+        count: u32,
+    }
+
+    // Software adapter, so the test doesn't depend on the GPU of the machine running it
+    fn fallback_adapter() -> Option<wgpu::Adapter> {
+        let instance = wgpu::Instance::new(wgpu::BackendBit::all());
+        instance.enumerate_adapters(wgpu::BackendBit::all())
+            .find(|adapter| adapter.get_info().device_type == wgpu::DeviceType::Cpu)
+    }
+
+    #[test]
+    fn runs_a_kernel_and_reads_the_results_back() {
+        let adapter = match fallback_adapter() {
+            Some(adapter) => adapter,
+            None => {
+                eprintln!("Skipping, no software adapter is available");
+                return;
+            }
+        };
+        let (device, queue) = block_on(adapter.request_device(&wgpu::DeviceDescriptor::default(), None)).unwrap();
+        let mut assets = AssetManager::new(env!("CARGO_MANIFEST_DIR"));
+        let shader = assets.load_shader(&device, "shaders/compute/scale.wgsl").unwrap();
+        let kernel = ComputeBuilder::new("Scale", &shader)
+            .workgroup_size([64, 1, 1])
+            .binding(ComputeBinding::Uniform)
+            .binding(ComputeBinding::Storage { read_only: false })
+            .build(&device, &assets);
+
+        // Not a multiple of the workgroup size, the last workgroup is partly idle
+        let input: Vec<u32> = (0..100).collect();
+        let params = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Scale Params"),
+            contents: bytemuck::cast_slice(&[ScaleParams { factor: 3, count: input.len() as u32 }]),
+            usage: wgpu::BufferUsage::UNIFORM,
+        });
+        let values = create_storage_buffer(&device, bytemuck::cast_slice(&input), "Scale Values");
+        let bind_group = kernel.bind_group(&device, &[params.as_entire_binding(), values.as_entire_binding()]);
+        assert_eq!(kernel.workgroups([input.len() as u32, 1, 1]), [2, 1, 1]);
+        kernel.run(&device, &queue, &bind_group, [input.len() as u32, 1, 1]);
+
+        let output = read_buffer(&device, &queue, &values, (input.len() * std::mem::size_of::<u32>()) as wgpu::BufferAddress).unwrap();
+        let output: &[u32] = bytemuck::cast_slice(&output);
+        let expected: Vec<u32> = input.iter().map(|value| value * 3).collect();
+        assert_eq!(output, expected.as_slice());
+    }
+}
@@ -0,0 +1,481 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::*;
+
+use crate::error::InitError;
+use crate::lod::LodMetric;
+use crate::post_process::TonemapOperator;
+
+// WebGPU only guarantees 1 and 4 samples for every format and wgpu 0.8 can't query the others,
+// so 2 and 8 would fail validation on some hardware
+pub const SAMPLE_COUNTS: [u32; 2] = [1, 4];
+
+// Startup options, from how the renderer picks its adapter and presents frames to what's loaded
+// into the scene.
+// Read from an optional config file of `key = value` lines, overridden by command line flags
+// of the same name, e.g. `--backend vulkan --present-mode mailbox`.
+#[derive(Debug, Clone)]
+pub struct RendererConfig {
+    pub backends: wgpu::BackendBit,
+    pub power_preference: wgpu::PowerPreference,
+    // Only consider software (CPU) adapters
+    pub force_fallback_adapter: bool,
+    // Only consider adapters whose name contains this, case insensitive
+    pub adapter_name: Option<String>,
+    pub present_mode: wgpu::PresentMode,
+    pub sample_count: u32,
+    // Print the available adapters and exit
+    pub list_adapters: bool,
+    // Time the render passes, on the GPU if the adapter supports timestamp queries
+    pub profile: bool,
+    // Where to write a Chrome trace of the profiled frames on exit, implies profile
+    pub trace_file: Option<PathBuf>,
+    // Save every frame as a numbered PNG in this directory
+    pub record: Option<PathBuf>,
+    pub tonemap: TonemapOperator,
+    pub exposure: f32,
+    // Linear RGB background, used when there's no skybox
+    pub clear_color: [f32; 3],
+    // A directory with px, nx, py, ny, pz and nz images, or an equirectangular panorama.
+    // Also used for image based lighting.
+    pub skybox: Option<PathBuf>,
+    // Tangent space normal map for the trees, flat without one
+    pub normal_map: Option<PathBuf>,
+    // What picks the trees' level of detail
+    pub lod_metric: LodMetric,
+    // Wavefront OBJ mesh shown next to the trees
+    pub model: Option<PathBuf>,
+    // Color grading lookup table, a strip of size square slices of a size^3 cube
+    pub lut: Option<PathBuf>,
+}
+
+impl Default for RendererConfig {
+    fn default() -> Self {
+        Self {
+            backends: wgpu::BackendBit::VULKAN,
+            power_preference: wgpu::PowerPreference::default(),
+            force_fallback_adapter: false,
+            adapter_name: None,
+            present_mode: wgpu::PresentMode::Fifo,
+            sample_count: 4,
+            list_adapters: false,
+            profile: false,
+            trace_file: None,
+            record: None,
+            tonemap: TonemapOperator::Aces,
+            exposure: 1.0,
+            clear_color: [0.1, 0.2, 0.3],
+            skybox: None,
+            normal_map: None,
+            lod_metric: LodMetric::Distance,
+            model: None,
+            lut: None,
+        }
+    }
+}
+
+pub const USAGE: &str = "\
+Options:
+    --config <path>             read options from a file of `option = value` lines
+    --backend <backends>        comma separated: vulkan, metal, dx12, dx11, gl, primary, secondary, all
+    --power <preference>        low or high
+    --fallback-adapter          only use a software adapter
+    --adapter <name>            only use adapters whose name contains <name>
+    --present-mode <mode>       fifo (vsync on), immediate (vsync off) or mailbox
+    --msaa <samples>            1 or 4
+    --list-adapters             print the available adapters and exit
+    --profile                   log how long each render pass takes
+    --trace-file <path>         profile and write a Chrome trace (chrome://tracing) to <path> on exit
+    --record <dir>              save every frame to <dir>/frame-00000.png, frame-00001.png, ...
+    --tonemap <operator>        reinhard, aces or uncharted2
+    --exposure <value>          scales the HDR scene before tonemapping
+    --clear-color <r,g,b>       linear background color without a skybox, e.g. 0.1,0.2,0.3
+    --skybox <path>             directory with px, nx, py, ny, pz and nz images, or a panorama (.hdr),
+                                also lights the scene
+    --normal-map <path>         tangent space normal map (linear RGB) for the trees
+    --lod-metric <metric>       distance from the camera or screen-size, picks the trees' level of detail
+    --model <path>              .obj mesh to show next to the trees
+    --lut <path>                color grading lookup table, a strip of square slices (e.g. 256x16)";
+
+impl RendererConfig {
+    // Skips the program name, like the iterator returned by std::env::args
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Self> {
+        let mut config = Self::default();
+        let args: Vec<String> = args.into_iter().skip(1).collect();
+
+        // The config file is applied first, so flags on the command line take precedence
+        if let Some(i) = args.iter().position(|arg| arg == "--config") {
+            let path = args.get(i + 1).ok_or_else(|| anyhow!("--config requires a path"))?;
+            config.apply_file(Path::new(path))?;
+        }
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let key = arg.strip_prefix("--")
+                .ok_or_else(|| anyhow!("Unexpected argument '{}'\n{}", arg, USAGE))?;
+            match key {
+                "fallback-adapter" | "list-adapters" | "profile" => config.set(key, "true")?,
+                "config" => {
+                    args.next();
+                }
+                _ => {
+                    let value = args.next()
+                        .ok_or_else(|| anyhow!("--{} requires a value\n{}", key, USAGE))?;
+                    config.set(key, value)?;
+                }
+            }
+        }
+
+        Ok(config)
+    }
+
+    pub fn apply_file(&mut self, path: &Path) -> Result<()> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        self.apply_str(&contents)
+            .with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    pub fn apply_str(&mut self, contents: &str) -> Result<()> {
+        for (number, line) in contents.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line.split_once('=')
+                .ok_or_else(|| anyhow!("Line {}: expected `option = value`", number + 1))?;
+            self.set(key.trim(), value.trim())
+                .with_context(|| format!("Line {}", number + 1))?;
+        }
+        Ok(())
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        match key {
+            "backend" => self.backends = parse_backends(value)?,
+            "power" => self.power_preference = parse_power_preference(value)?,
+            "fallback-adapter" => self.force_fallback_adapter = parse_bool(value)?,
+            "adapter" => self.adapter_name = Some(value.to_string()),
+            "present-mode" => self.present_mode = parse_present_mode(value)?,
+            "msaa" => self.sample_count = parse_sample_count(value)?,
+            "list-adapters" => self.list_adapters = parse_bool(value)?,
+            "profile" => self.profile = parse_bool(value)?,
+            "trace-file" => {
+                self.trace_file = Some(PathBuf::from(value));
+                self.profile = true;
+            }
+            "record" => self.record = Some(PathBuf::from(value)),
+            "tonemap" => self.tonemap = parse_tonemap_operator(value)?,
+            "exposure" => self.exposure = parse_exposure(value)?,
+            "clear-color" => self.clear_color = parse_color(value)?,
+            "skybox" => self.skybox = Some(PathBuf::from(value)),
+            "normal-map" => self.normal_map = Some(PathBuf::from(value)),
+            "lod-metric" => self.lod_metric = parse_lod_metric(value)?,
+            "model" => self.model = Some(PathBuf::from(value)),
+            "lut" => self.lut = Some(PathBuf::from(value)),
+            _ => bail!("Unknown option '{}'\n{}", key, USAGE),
+        }
+        Ok(())
+    }
+
+    // Whether the adapter satisfies the hard requirements of the config
+    pub fn accepts(&self, info: &wgpu::AdapterInfo) -> bool {
+        if self.force_fallback_adapter && info.device_type != wgpu::DeviceType::Cpu {
+            return false;
+        }
+        match &self.adapter_name {
+            Some(name) => info.name.to_lowercase().contains(&name.to_lowercase()),
+            None => true,
+        }
+    }
+}
+
+// Options are relative to the working directory, unlike asset paths which are relative to the asset root
+pub fn working_dir_path(path: &Path) -> PathBuf {
+    std::env::current_dir()
+        .map(|dir| dir.join(path))
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+// `#` only starts a comment at the beginning of a line or after whitespace, so values can contain it
+fn strip_comment(line: &str) -> &str {
+    // The start of the line counts as whitespace
+    let mut previous = ' ';
+    for (i, c) in line.char_indices() {
+        if c == '#' && previous.is_whitespace() {
+            return &line[..i];
+        }
+        previous = c;
+    }
+    line
+}
+
+pub fn parse_backends(value: &str) -> Result<wgpu::BackendBit> {
+    let mut backends = wgpu::BackendBit::empty();
+    for name in value.split(',') {
+        backends |= match name.trim().to_lowercase().as_str() {
+            "vulkan" => wgpu::BackendBit::VULKAN,
+            "metal" => wgpu::BackendBit::METAL,
+            "dx12" => wgpu::BackendBit::DX12,
+            "dx11" => wgpu::BackendBit::DX11,
+            "gl" => wgpu::BackendBit::GL,
+            "primary" => wgpu::BackendBit::PRIMARY,
+            "secondary" => wgpu::BackendBit::SECONDARY,
+            "all" => wgpu::BackendBit::all(),
+            other => bail!("Unknown backend '{}', expected vulkan, metal, dx12, dx11, gl, primary, secondary or all", other),
+        };
+    }
+    Ok(backends)
+}
+
+pub fn parse_power_preference(value: &str) -> Result<wgpu::PowerPreference> {
+    match value.to_lowercase().as_str() {
+        "low" | "low-power" => Ok(wgpu::PowerPreference::LowPower),
+        "high" | "high-performance" => Ok(wgpu::PowerPreference::HighPerformance),
+        _ => bail!("Unknown power preference '{}', expected low or high", value),
+    }
+}
+
+pub fn parse_present_mode(value: &str) -> Result<wgpu::PresentMode> {
+    match value.to_lowercase().as_str() {
+        "fifo" | "vsync" | "on" => Ok(wgpu::PresentMode::Fifo),
+        "immediate" | "off" => Ok(wgpu::PresentMode::Immediate),
+        "mailbox" => Ok(wgpu::PresentMode::Mailbox),
+        _ => bail!("Unknown present mode '{}', expected fifo, immediate or mailbox", value),
+    }
+}
+
+fn parse_sample_count(value: &str) -> Result<u32> {
+    match value.parse() {
+        std::result::Result::Ok(count) if SAMPLE_COUNTS.contains(&count) => Ok(count),
+        _ => bail!("Invalid MSAA sample count '{}', expected 1 or 4", value),
+    }
+}
+
+pub fn parse_tonemap_operator(value: &str) -> Result<TonemapOperator> {
+    match value.to_lowercase().as_str() {
+        "reinhard" => Ok(TonemapOperator::Reinhard),
+        "aces" => Ok(TonemapOperator::Aces),
+        "uncharted2" | "uncharted" | "hable" => Ok(TonemapOperator::Uncharted2),
+        _ => bail!("Unknown tonemap operator '{}', expected reinhard, aces or uncharted2", value),
+    }
+}
+
+fn parse_lod_metric(value: &str) -> Result<LodMetric> {
+    match value.to_lowercase().as_str() {
+        "distance" => Ok(LodMetric::Distance),
+        "screen-size" | "screen" => Ok(LodMetric::ScreenSize),
+        _ => bail!("Unknown LOD metric '{}', expected distance or screen-size", value),
+    }
+}
+
+fn parse_exposure(value: &str) -> Result<f32> {
+    match value.parse::<f32>() {
+        std::result::Result::Ok(exposure) if exposure > 0.0 => Ok(exposure),
+        _ => bail!("Invalid exposure '{}', expected a positive number", value),
+    }
+}
+
+fn parse_color(value: &str) -> Result<[f32; 3]> {
+    let channels: Vec<f32> = value.split(',')
+        .map(|channel| channel.trim().parse::<f32>())
+        .collect::<std::result::Result<_, _>>()
+        .map_err(|_| anyhow!("Invalid color '{}', expected r,g,b", value))?;
+    match channels.as_slice() {
+        [r, g, b] if channels.iter().all(|channel| *channel >= 0.0) => Ok([*r, *g, *b]),
+        _ => bail!("Invalid color '{}', expected three non-negative numbers r,g,b", value),
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Ok(true),
+        "false" | "no" | "off" | "0" => Ok(false),
+        _ => bail!("Expected true or false, got '{}'", value),
+    }
+}
+
+// Picks the adapter to render to `surface` with. Without hard requirements wgpu gets to choose
+// based on the power preference, otherwise the first matching adapter is used.
+pub async fn select_adapter(
+    instance: &wgpu::Instance,
+    surface: &wgpu::Surface,
+    config: &RendererConfig
+) -> std::result::Result<wgpu::Adapter, InitError> {
+    if !config.force_fallback_adapter && config.adapter_name.is_none() {
+        let adapter = instance.request_adapter(
+            &wgpu::RequestAdapterOptions {
+                power_preference: config.power_preference,
+                compatible_surface: Some(surface),
+            },
+        ).await;
+        return adapter.ok_or_else(|| InitError::NoAdapter(no_adapter_message(config, &describe_adapters())));
+    }
+
+    instance.enumerate_adapters(config.backends)
+        .find(|adapter| {
+            config.accepts(&adapter.get_info())
+                && adapter.get_swap_chain_preferred_format(surface).is_some()
+        })
+        .ok_or_else(|| InitError::NoAdapter(no_adapter_message(config, &describe_adapters())))
+}
+
+fn no_adapter_message(config: &RendererConfig, available: &[String]) -> String {
+    let mut message = format!(
+        "No adapter supporting the window matches backends {:?}{}{}",
+        config.backends,
+        if config.force_fallback_adapter { ", fallback adapter" } else { "" },
+        config.adapter_name.as_ref().map(|name| format!(", name containing '{}'", name)).unwrap_or_default(),
+    );
+    if available.is_empty() {
+        message.push_str("\nNo adapters are available on any backend");
+    } else {
+        message.push_str("\nAvailable adapters:");
+        for adapter in available {
+            message.push_str("\n    ");
+            message.push_str(adapter);
+        }
+    }
+    message
+}
+
+// One line per adapter on any backend
+pub fn describe_adapters() -> Vec<String> {
+    let instance = wgpu::Instance::new(wgpu::BackendBit::all());
+    instance.enumerate_adapters(wgpu::BackendBit::all())
+        .map(|adapter| {
+            let info = adapter.get_info();
+            format!("{} ({:?}, {:?})", info.name, info.backend, info.device_type)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        std::iter::once("wgpu_rs_custom_engine").chain(args.iter().copied()).map(String::from).collect()
+    }
+
+    fn adapter(name: &str, device_type: wgpu::DeviceType) -> wgpu::AdapterInfo {
+        wgpu::AdapterInfo {
+            name: name.to_string(),
+            vendor: 0,
+            device: 0,
+            device_type,
+            backend: wgpu::Backend::Vulkan,
+        }
+    }
+
+    #[test]
+    fn parses_command_line() {
+        let config = RendererConfig::from_args(args(&["--backend", "vulkan,gl", "--present-mode", "mailbox", "--fallback-adapter"])).unwrap();
+        assert_eq!(config.backends, wgpu::BackendBit::VULKAN | wgpu::BackendBit::GL);
+        assert_eq!(config.present_mode, wgpu::PresentMode::Mailbox);
+        assert!(config.force_fallback_adapter);
+        assert!(!config.profile);
+    }
+
+    #[test]
+    fn parses_tonemapping() {
+        let config = RendererConfig::from_args(args(&["--tonemap", "Uncharted2", "--exposure", "1.5"])).unwrap();
+        assert_eq!(config.tonemap, TonemapOperator::Uncharted2);
+        assert_eq!(config.exposure, 1.5);
+        assert!(RendererConfig::from_args(args(&["--exposure", "-1"])).is_err());
+        assert!(RendererConfig::from_args(args(&["--tonemap", "linear"])).is_err());
+    }
+
+    #[test]
+    fn parses_normal_map() {
+        let config = RendererConfig::from_args(args(&["--normal-map", "res/bark_normal.png"])).unwrap();
+        assert_eq!(config.normal_map, Some(PathBuf::from("res/bark_normal.png")));
+        assert_eq!(RendererConfig::default().normal_map, None);
+    }
+
+    #[test]
+    fn parses_lod_metric() {
+        assert_eq!(RendererConfig::default().lod_metric, LodMetric::Distance);
+        let config = RendererConfig::from_args(args(&["--lod-metric", "screen-size"])).unwrap();
+        assert_eq!(config.lod_metric, LodMetric::ScreenSize);
+        assert!(RendererConfig::from_args(args(&["--lod-metric", "triangles"])).is_err());
+    }
+
+    #[test]
+    fn parses_clear_color() {
+        let config = RendererConfig::from_args(args(&["--clear-color", "0, 0.5, 1"])).unwrap();
+        assert_eq!(config.clear_color, [0.0, 0.5, 1.0]);
+        assert!(RendererConfig::from_args(args(&["--clear-color", "1,1"])).is_err());
+        assert!(RendererConfig::from_args(args(&["--clear-color", "red"])).is_err());
+    }
+
+    #[test]
+    fn trace_file_enables_profiling() {
+        let config = RendererConfig::from_args(args(&["--trace-file", "trace.json"])).unwrap();
+        assert!(config.profile);
+        assert_eq!(config.trace_file, Some(PathBuf::from("trace.json")));
+    }
+
+    #[test]
+    fn parses_model_and_lut() {
+        let config = RendererConfig::from_args(args(&["--model", "res/cube.obj", "--lut", "res/lut.png"])).unwrap();
+        assert_eq!(config.model, Some(PathBuf::from("res/cube.obj")));
+        assert_eq!(config.lut, Some(PathBuf::from("res/lut.png")));
+        assert_eq!(RendererConfig::default().model, None);
+    }
+
+    #[test]
+    fn rejects_invalid_options() {
+        assert!(RendererConfig::from_args(args(&["--backend", "opengl"])).is_err());
+        assert!(RendererConfig::from_args(args(&["--msaa", "3"])).is_err());
+        assert!(RendererConfig::from_args(args(&["--msaa", "8"])).is_err());
+        assert!(RendererConfig::from_args(args(&["--present-mode"])).is_err());
+        assert!(RendererConfig::from_args(args(&["--config", "does-not-exist.cfg"])).is_err());
+        assert!(RendererConfig::default().apply_str("vsync").is_err());
+    }
+
+    #[test]
+    fn config_file_options() {
+        let mut config = RendererConfig::default();
+        config.apply_str("# Software rendering\nfallback-adapter = true\npower = high\n").unwrap();
+        assert!(config.force_fallback_adapter);
+        assert_eq!(config.power_preference, wgpu::PowerPreference::HighPerformance);
+    }
+
+    #[test]
+    fn config_file_values_can_contain_hashes() {
+        let mut config = RendererConfig::default();
+        config.apply_str("adapter = GPU#2 # the second one\n").unwrap();
+        assert_eq!(config.adapter_name.as_deref(), Some("GPU#2"));
+    }
+
+    #[test]
+    fn filters_adapters() {
+        let mut config = RendererConfig::default();
+        assert!(config.accepts(&adapter("GeForce", wgpu::DeviceType::DiscreteGpu)));
+
+        config.adapter_name = Some("geforce".to_string());
+        assert!(config.accepts(&adapter("NVIDIA GeForce", wgpu::DeviceType::DiscreteGpu)));
+        assert!(!config.accepts(&adapter("Intel UHD", wgpu::DeviceType::IntegratedGpu)));
+
+        config.adapter_name = None;
+        config.force_fallback_adapter = true;
+        assert!(config.accepts(&adapter("llvmpipe", wgpu::DeviceType::Cpu)));
+        assert!(!config.accepts(&adapter("GeForce", wgpu::DeviceType::DiscreteGpu)));
+    }
+
+    #[test]
+    fn no_adapter_message_lists_available_adapters() {
+        let config = RendererConfig {
+            force_fallback_adapter: true,
+            ..Default::default()
+        };
+
+        let message = no_adapter_message(&config, &["GeForce (Vulkan, DiscreteGpu)".to_string()]);
+        assert!(message.contains("fallback adapter"));
+        assert!(message.contains("GeForce (Vulkan, DiscreteGpu)"));
+
+        let message = no_adapter_message(&config, &[]);
+        assert!(message.contains("No adapters are available"));
+    }
+}
@@ -0,0 +1,222 @@
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Transform, Vector3, Vector4};
+
+// Axis aligned bounding box
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    // Smallest box around the points, a box at the origin with no size if there are none
+    pub fn from_points<I: IntoIterator<Item = Point3<f32>>>(points: I) -> Self {
+        let mut points = points.into_iter();
+        let first = match points.next() {
+            Some(point) => point,
+            None => return Self { min: Point3::origin(), max: Point3::origin() },
+        };
+        points.fold(Self { min: first, max: first }, |aabb, point| Self {
+            min: Point3::new(aabb.min.x.min(point.x), aabb.min.y.min(point.y), aabb.min.z.min(point.z)),
+            max: Point3::new(aabb.max.x.max(point.x), aabb.max.y.max(point.y), aabb.max.z.max(point.z)),
+        })
+    }
+
+    pub fn center(&self) -> Point3<f32> {
+        self.min.midpoint(self.max)
+    }
+
+    // Half the size along each axis
+    pub fn extents(&self) -> Vector3<f32> {
+        (self.max - self.min) * 0.5
+    }
+
+    // Box around the transformed box, which grows when the transform rotates it
+    pub fn transform(&self, transform: Matrix4<f32>) -> Self {
+        let center = transform.transform_point(self.center());
+        let extents = self.extents();
+        // Each axis of the new box is as long as the absolute projections of the old axes on it
+        let extents = Vector3::new(
+            transform.x.x.abs() * extents.x + transform.y.x.abs() * extents.y + transform.z.x.abs() * extents.z,
+            transform.x.y.abs() * extents.x + transform.y.y.abs() * extents.y + transform.z.y.abs() * extents.z,
+            transform.x.z.abs() * extents.x + transform.y.z.abs() * extents.y + transform.z.z.abs() * extents.z,
+        );
+        Self { min: center - extents, max: center + extents }
+    }
+
+    pub fn bounding_sphere(&self) -> BoundingSphere {
+        BoundingSphere { center: self.center(), radius: self.extents().magnitude() }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Point3<f32>,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    // The radius grows with the largest scale of the transform, so it stays conservative
+    pub fn transform(&self, transform: Matrix4<f32>) -> Self {
+        let scale = transform.x.truncate().magnitude()
+            .max(transform.y.truncate().magnitude())
+            .max(transform.z.truncate().magnitude());
+        Self { center: transform.transform_point(self.center), radius: self.radius * scale }
+    }
+}
+
+// Points with a positive distance are on the side the normal points to
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Plane {
+    pub normal: Vector3<f32>,
+    pub d: f32,
+}
+
+impl Plane {
+    // Normalized so distance returns actual distances
+    fn from_coefficients(coefficients: Vector4<f32>) -> Self {
+        let length = coefficients.truncate().magnitude();
+        Self { normal: coefficients.truncate() / length, d: coefficients.w / length }
+    }
+
+    pub fn distance(&self, point: Point3<f32>) -> f32 {
+        self.normal.dot(point.to_vec()) + self.d
+    }
+}
+
+// The six planes around the volume a view projection matrix sees, with their normals pointing inward
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Frustum {
+    // Left, right, bottom, top, near, far
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    // Takes a matrix like Camera::build_view_projection_matrix, which maps depth to 0..1 as wgpu
+    // expects instead of OpenGL's -1..1. A point is inside when -w <= x <= w, -w <= y <= w and
+    // 0 <= z <= w after the transform, every inequality is one plane.
+    pub fn from_view_projection(view_projection: Matrix4<f32>) -> Self {
+        // cgmath matrices are stored by column
+        let row = |i: usize| Vector4::new(view_projection.x[i], view_projection.y[i], view_projection.z[i], view_projection.w[i]);
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        Self {
+            planes: [
+                Plane::from_coefficients(w + x),
+                Plane::from_coefficients(w - x),
+                Plane::from_coefficients(w + y),
+                Plane::from_coefficients(w - y),
+                Plane::from_coefficients(z),
+                Plane::from_coefficients(w - z),
+            ],
+        }
+    }
+
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes.iter().all(|plane| plane.distance(sphere.center) >= -sphere.radius)
+    }
+
+    // Conservative, boxes near the frustum's corners can pass without being visible
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        let center = aabb.center();
+        let extents = aabb.extents();
+        self.planes.iter().all(|plane| {
+            // How far the box reaches towards the plane's normal from its center
+            let reach = plane.normal.x.abs() * extents.x + plane.normal.y.abs() * extents.y + plane.normal.z.abs() * extents.z;
+            plane.distance(center) >= -reach
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Deg, SquareMatrix};
+
+    use super::*;
+    use crate::camera::OPENGL_TO_WGPU_MATRIX;
+
+    const EPSILON: f32 = 1e-4;
+
+    // Looking down -z from the origin, near plane at 1 and far plane at 10
+    fn frustum() -> Frustum {
+        Frustum::from_view_projection(OPENGL_TO_WGPU_MATRIX * cgmath::perspective(Deg(90.0), 1.0, 1.0, 10.0))
+    }
+
+    fn contains_point(frustum: &Frustum, point: Point3<f32>) -> bool {
+        frustum.intersects_sphere(&BoundingSphere { center: point, radius: 0.0 })
+    }
+
+    #[test]
+    fn extracts_normalized_planes() {
+        let frustum = frustum();
+        let [left, right, bottom, top, near, far] = frustum.planes;
+        let diagonal = std::f32::consts::FRAC_1_SQRT_2;
+        assert!((left.normal - Vector3::new(diagonal, 0.0, -diagonal)).magnitude() < EPSILON);
+        assert!((right.normal - Vector3::new(-diagonal, 0.0, -diagonal)).magnitude() < EPSILON);
+        assert!((bottom.normal - Vector3::new(0.0, diagonal, -diagonal)).magnitude() < EPSILON);
+        assert!((top.normal - Vector3::new(0.0, -diagonal, -diagonal)).magnitude() < EPSILON);
+        assert!(left.d.abs() < EPSILON);
+        // The depth planes come out at the actual near and far distances, not halfway to the near plane
+        assert!((near.normal - Vector3::new(0.0, 0.0, -1.0)).magnitude() < EPSILON);
+        assert!((near.d + 1.0).abs() < EPSILON);
+        assert!((far.normal - Vector3::new(0.0, 0.0, 1.0)).magnitude() < EPSILON);
+        assert!((far.d - 10.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn contains_points_between_the_planes() {
+        let frustum = frustum();
+        assert!(contains_point(&frustum, Point3::new(0.0, 0.0, -5.0)));
+        assert!(contains_point(&frustum, Point3::new(4.9, -4.9, -5.0)));
+        assert!(!contains_point(&frustum, Point3::new(0.0, 0.0, -0.5)));
+        assert!(!contains_point(&frustum, Point3::new(0.0, 0.0, -10.5)));
+        assert!(!contains_point(&frustum, Point3::new(5.1, 0.0, -5.0)));
+        assert!(!contains_point(&frustum, Point3::new(0.0, 0.0, 5.0)));
+    }
+
+    #[test]
+    fn planes_follow_the_view() {
+        // Camera at (0, 0, 5) looking down -z sees the origin but not points behind it
+        let view = Matrix4::from_translation(Vector3::new(0.0, 0.0, 5.0)).invert().unwrap();
+        let frustum = Frustum::from_view_projection(OPENGL_TO_WGPU_MATRIX * cgmath::perspective(Deg(90.0), 1.0, 1.0, 10.0) * view);
+        assert!(contains_point(&frustum, Point3::new(0.0, 0.0, 0.0)));
+        assert!(!contains_point(&frustum, Point3::new(0.0, 0.0, 6.0)));
+    }
+
+    #[test]
+    fn intersects_spheres() {
+        let frustum = frustum();
+        let sphere = |x: f32, y: f32, z: f32, radius: f32| BoundingSphere { center: Point3::new(x, y, z), radius };
+        assert!(frustum.intersects_sphere(&sphere(0.0, 0.0, -5.0, 1.0)));
+        // Straddling the near plane
+        assert!(frustum.intersects_sphere(&sphere(0.0, 0.0, 0.0, 1.5)));
+        assert!(!frustum.intersects_sphere(&sphere(0.0, 0.0, 2.0, 1.5)));
+        assert!(!frustum.intersects_sphere(&sphere(0.0, 0.0, -12.0, 1.5)));
+        assert!(!frustum.intersects_sphere(&sphere(10.0, 0.0, -5.0, 1.0)));
+    }
+
+    #[test]
+    fn intersects_boxes() {
+        let frustum = frustum();
+        let aabb = |min: [f32; 3], max: [f32; 3]| Aabb { min: min.into(), max: max.into() };
+        assert!(frustum.intersects_aabb(&aabb([-1.0, -1.0, -6.0], [1.0, 1.0, -4.0])));
+        // Contains the whole frustum
+        assert!(frustum.intersects_aabb(&aabb([-100.0, -100.0, -100.0], [100.0, 100.0, 100.0])));
+        assert!(frustum.intersects_aabb(&aabb([4.0, -1.0, -6.0], [6.0, 1.0, -4.0])));
+        assert!(!frustum.intersects_aabb(&aabb([6.0, -1.0, -6.0], [8.0, 1.0, -4.0])));
+        assert!(!frustum.intersects_aabb(&aabb([-1.0, -1.0, 1.0], [1.0, 1.0, 2.0])));
+    }
+
+    #[test]
+    fn bounds_follow_transforms() {
+        let aabb = Aabb::from_points(vec![Point3::new(-1.0, -2.0, 0.0), Point3::new(1.0, 2.0, 0.5), Point3::new(0.0, 0.0, -0.5)]);
+        assert_eq!(aabb, Aabb { min: Point3::new(-1.0, -2.0, -0.5), max: Point3::new(1.0, 2.0, 0.5) });
+
+        let transform = Matrix4::from_translation(Vector3::new(5.0, 0.0, 0.0)) * Matrix4::from_angle_z(Deg(90.0)) * Matrix4::from_scale(2.0);
+        let moved = aabb.transform(transform);
+        assert!((moved.min - Point3::new(1.0, -2.0, -1.0)).magnitude() < EPSILON);
+        assert!((moved.max - Point3::new(9.0, 2.0, 1.0)).magnitude() < EPSILON);
+
+        let sphere = aabb.bounding_sphere().transform(transform);
+        assert!((sphere.center - Point3::new(5.0, 0.0, 0.0)).magnitude() < EPSILON);
+        assert!((sphere.radius - 2.0 * aabb.extents().magnitude()).abs() < EPSILON);
+    }
+}
@@ -0,0 +1,328 @@
+use std::sync::Arc;
+
+use anyhow::*;
+use cgmath::{InnerSpace, Matrix4, Point3, SquareMatrix, Transform, Vector3};
+
+use crate::asset_manager::{AssetManager, Handle};
+use crate::pipeline::{DepthSettings, PipelineBuilder, PipelineCache};
+use crate::texture::Texture;
+
+pub type Color = [f32; 4];
+
+pub const RED: Color = [1.0, 0.0, 0.0, 1.0];
+pub const GREEN: Color = [0.0, 1.0, 0.0, 1.0];
+pub const BLUE: Color = [0.0, 0.0, 1.0, 1.0];
+pub const YELLOW: Color = [1.0, 1.0, 0.0, 1.0];
+pub const WHITE: Color = [1.0, 1.0, 1.0, 1.0];
+pub const GREY: Color = [0.5, 0.5, 0.5, 1.0];
+pub const CYAN: Color = [0.0, 1.0, 1.0, 1.0];
+
+// Line segments per circle when drawing spheres
+const CIRCLE_SEGMENTS: usize = 32;
+
+// Room for this many lines before the vertex buffer has to grow
+const INITIAL_CAPACITY: usize = 1024;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DebugVertex {
+    pub position: [f32; 3],
+    pub color: Color,
+}
+
+impl DebugVertex {
+    pub(crate) fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<DebugVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ]
+        }
+    }
+}
+
+// Immediate mode line drawing for debugging. Shapes can be added from anywhere during a frame,
+// they are uploaded by prepare, drawn on top of the scene by draw and then forgotten, so
+// anything that should stay visible has to be added again every frame.
+pub struct DebugDraw {
+    vertices: Vec<DebugVertex>,
+    // Number of vertices uploaded by the last prepare
+    vertex_count: u32,
+    vertex_buffer: wgpu::Buffer,
+    // In vertices
+    capacity: usize,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    pipeline_layout: Arc<wgpu::PipelineLayout>,
+    shader: Handle<wgpu::ShaderModule>,
+    pipeline: Arc<wgpu::RenderPipeline>,
+    // Whether lines are hidden behind the scene or always drawn in front
+    depth_test: bool,
+    color_format: wgpu::TextureFormat,
+    sample_count: u32,
+}
+
+impl DebugDraw {
+    pub fn new(
+        device: &wgpu::Device,
+        assets: &mut AssetManager,
+        cache: &mut PipelineCache,
+        color_format: wgpu::TextureFormat,
+        sample_count: u32
+    ) -> Result<Self> {
+        let shader = assets.load_shader(device, "shaders/debug.wgsl")?;
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Debug Draw Uniform Buffer"),
+            size: std::mem::size_of::<[[f32; 4]; 4]>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }
+            ],
+            label: Some("debug_draw_bind_group_layout"),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                }
+            ],
+            label: Some("debug_draw_bind_group"),
+        });
+        let pipeline_layout = Arc::new(
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Debug Draw Pipeline Layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            })
+        );
+
+        let capacity = INITIAL_CAPACITY * 2;
+        let vertex_buffer = Self::create_vertex_buffer(device, capacity);
+        let pipeline = Self::build_pipeline(device, assets, cache, &pipeline_layout, &shader, color_format, sample_count, true);
+
+        Ok(Self {
+            vertices: Vec::with_capacity(capacity),
+            vertex_count: 0,
+            vertex_buffer,
+            capacity,
+            uniform_buffer,
+            bind_group,
+            pipeline_layout,
+            shader,
+            pipeline,
+            depth_test: true,
+            color_format,
+            sample_count,
+        })
+    }
+
+    fn create_vertex_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Debug Draw Vertex Buffer"),
+            size: (capacity * std::mem::size_of::<DebugVertex>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn build_pipeline(
+        device: &wgpu::Device,
+        assets: &AssetManager,
+        cache: &mut PipelineCache,
+        layout: &Arc<wgpu::PipelineLayout>,
+        shader: &Handle<wgpu::ShaderModule>,
+        color_format: wgpu::TextureFormat,
+        sample_count: u32,
+        depth_test: bool
+    ) -> Arc<wgpu::RenderPipeline> {
+        PipelineBuilder::new("Debug Draw Pipeline", layout, shader, color_format)
+            .vertex_layout(DebugVertex::desc())
+            .topology(wgpu::PrimitiveTopology::LineList)
+            .cull_mode(None)
+            .blend(Some(wgpu::BlendState::ALPHA_BLENDING))
+            // Has to match the depth attachment of the scene pass even when not testing against it
+            .depth(Some(DepthSettings {
+                format: Texture::DEPTH_FORMAT,
+                write_enabled: false,
+                compare: if depth_test { wgpu::CompareFunction::LessEqual } else { wgpu::CompareFunction::Always },
+            }))
+            .sample_count(sample_count)
+            .build(device, assets, cache)
+    }
+
+    // Call when the swap chain format or sample count of the scene pass changes
+    pub fn rebuild_pipeline(
+        &mut self,
+        device: &wgpu::Device,
+        assets: &AssetManager,
+        cache: &mut PipelineCache,
+        color_format: wgpu::TextureFormat,
+        sample_count: u32
+    ) {
+        self.color_format = color_format;
+        self.sample_count = sample_count;
+        self.pipeline = Self::build_pipeline(device, assets, cache, &self.pipeline_layout, &self.shader, color_format, sample_count, self.depth_test);
+    }
+
+    pub fn depth_test(&self) -> bool {
+        self.depth_test
+    }
+
+    pub fn set_depth_test(&mut self, device: &wgpu::Device, assets: &AssetManager, cache: &mut PipelineCache, depth_test: bool) {
+        self.depth_test = depth_test;
+        self.rebuild_pipeline(device, assets, cache, self.color_format, self.sample_count);
+    }
+
+    // Number of lines added since the last prepare
+    pub fn line_count(&self) -> usize {
+        self.vertices.len() / 2
+    }
+
+    pub fn line(&mut self, from: Point3<f32>, to: Point3<f32>, color: Color) {
+        self.vertices.push(DebugVertex { position: from.into(), color });
+        self.vertices.push(DebugVertex { position: to.into(), color });
+    }
+
+    // Red, green and blue lines along the x, y and z axes of the transform
+    pub fn axes(&mut self, transform: Matrix4<f32>, length: f32) {
+        let origin = transform.transform_point(Point3::new(0.0, 0.0, 0.0));
+        self.line(origin, transform.transform_point(Point3::new(length, 0.0, 0.0)), RED);
+        self.line(origin, transform.transform_point(Point3::new(0.0, length, 0.0)), GREEN);
+        self.line(origin, transform.transform_point(Point3::new(0.0, 0.0, length)), BLUE);
+    }
+
+    // Axis aligned bounding box
+    pub fn aabb(&mut self, min: Point3<f32>, max: Point3<f32>, color: Color) {
+        self.oriented_box(Matrix4::identity(), min, max, color);
+    }
+
+    // The box between min and max in the local space of transform
+    pub fn oriented_box(&mut self, transform: Matrix4<f32>, min: Point3<f32>, max: Point3<f32>, color: Color) {
+        // Corner i takes x, y and z from max when bit 0, 1 and 2 of i are set
+        let corners: Vec<Point3<f32>> = (0..8)
+            .map(|i| Point3::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            ))
+            .map(|corner| transform.transform_point(corner))
+            .collect();
+        self.box_edges(&corners, color);
+    }
+
+    // The view volume of a view projection matrix, e.g. from Camera::build_view_projection_matrix
+    pub fn frustum(&mut self, view_projection: Matrix4<f32>, color: Color) {
+        let inverse = match view_projection.invert() {
+            Some(inverse) => inverse,
+            None => return,
+        };
+        // wgpu clip space has depth in 0..1
+        let corners: Vec<Point3<f32>> = (0..8)
+            .map(|i| Point3::new(
+                if i & 1 == 0 { -1.0 } else { 1.0 },
+                if i & 2 == 0 { -1.0 } else { 1.0 },
+                if i & 4 == 0 { 0.0 } else { 1.0 },
+            ))
+            .map(|corner| inverse.transform_point(corner))
+            .collect();
+        self.box_edges(&corners, color);
+    }
+
+    // Draws the 12 edges between corners ordered like in oriented_box
+    fn box_edges(&mut self, corners: &[Point3<f32>], color: Color) {
+        for i in 0..8 {
+            for bit in [1, 2, 4].iter() {
+                if i & bit == 0 {
+                    self.line(corners[i], corners[i | bit], color);
+                }
+            }
+        }
+    }
+
+    // Grid on the xz plane centered on center, with cells of cell_size
+    pub fn grid(&mut self, center: Point3<f32>, cell_size: f32, cells: u32, color: Color) {
+        let half = cell_size * cells as f32 / 2.0;
+        for i in 0..=cells {
+            let offset = -half + i as f32 * cell_size;
+            self.line(center + Vector3::new(offset, 0.0, -half), center + Vector3::new(offset, 0.0, half), color);
+            self.line(center + Vector3::new(-half, 0.0, offset), center + Vector3::new(half, 0.0, offset), color);
+        }
+    }
+
+    pub fn circle(&mut self, center: Point3<f32>, normal: Vector3<f32>, radius: f32, color: Color) {
+        let normal = normal.normalize();
+        // Any vector that isn't parallel to the normal works for building the circle's plane
+        let helper = if normal.x.abs() < 0.9 { Vector3::unit_x() } else { Vector3::unit_y() };
+        let u = normal.cross(helper).normalize() * radius;
+        let v = normal.cross(u);
+
+        let point = |i: usize| {
+            let angle = i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+            center + u * angle.cos() + v * angle.sin()
+        };
+        for i in 0..CIRCLE_SEGMENTS {
+            self.line(point(i), point(i + 1), color);
+        }
+    }
+
+    // Drawn as three circles around the x, y and z axes
+    pub fn sphere(&mut self, center: Point3<f32>, radius: f32, color: Color) {
+        self.circle(center, Vector3::unit_x(), radius, color);
+        self.circle(center, Vector3::unit_y(), radius, color);
+        self.circle(center, Vector3::unit_z(), radius, color);
+    }
+
+    // Uploads everything added since the last call and starts a new batch
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, view_projection: Matrix4<f32>) {
+        if self.vertices.len() > self.capacity {
+            self.capacity = self.vertices.len().next_power_of_two();
+            self.vertex_buffer = Self::create_vertex_buffer(device, self.capacity);
+        }
+        if !self.vertices.is_empty() {
+            queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&self.vertices));
+        }
+        let view_projection: [[f32; 4]; 4] = view_projection.into();
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[view_projection]));
+
+        self.vertex_count = self.vertices.len() as u32;
+        self.vertices.clear();
+    }
+
+    // Call inside the scene pass, after the scene has been drawn
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if self.vertex_count == 0 {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.draw(0..self.vertex_count, 0..1);
+    }
+}
@@ -0,0 +1,221 @@
+use anyhow::*;
+use wgpu::util::DeviceExt;
+
+use crate::asset_manager::{AssetManager, Handle};
+use crate::compute::{ComputeBinding, ComputeBuilder, ComputeKernel};
+use crate::texture::Texture;
+
+pub const IRRADIANCE_SIZE: u32 = 32;
+pub const PREFILTERED_SIZE: u32 = 128;
+// shaders/shader.wgsl samples up to mip level PREFILTERED_MIP_LEVELS - 1
+pub const PREFILTERED_MIP_LEVELS: u32 = 5;
+pub const BRDF_LUT_SIZE: u32 = 256;
+
+const WORKGROUP_SIZE: u32 = 8;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PrefilterParams {
+    roughness: f32,
+    _padding: [f32; 3],
+}
+
+// Image based lighting precomputed from an environment cube map on the GPU:
+//   irradiance:  diffuse light for every normal direction
+//   prefiltered: specular reflections, blurrier with every mip level as the roughness grows
+//   brdf_lut:    scale and bias applied to F0 for every n dot v and roughness
+// Materials read them through bind_group, laid out as create_bind_group_layout describes.
+pub struct Environment {
+    // Kept alive for the bind group
+    _irradiance: Texture,
+    _prefiltered: Texture,
+    _brdf_lut: Texture,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Environment {
+    pub fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let texture = |binding, view_dimension| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                texture(0, wgpu::TextureViewDimension::Cube),
+                texture(1, wgpu::TextureViewDimension::Cube),
+                texture(2, wgpu::TextureViewDimension::D2),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                        filtering: true,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("environment_bind_group_layout"),
+        })
+    }
+
+    // Lights everything evenly with color, what the scene looked like before image based lighting
+    pub fn uniform(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        assets: &mut AssetManager,
+        layout: &wgpu::BindGroupLayout,
+        color: [f32; 3]
+    ) -> Result<Self> {
+        let cube = Texture::uniform_cube(device, queue, color, "uniform_environment");
+        Self::from_cube(device, queue, assets, layout, &cube)
+    }
+
+    // Precomputes the lighting of an environment cube map, see Texture::cube_from_equirectangular
+    pub fn from_cube(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        assets: &mut AssetManager,
+        layout: &wgpu::BindGroupLayout,
+        environment: &Texture
+    ) -> Result<Self> {
+        let irradiance_shader = assets.load_shader(device, "shaders/ibl/irradiance.wgsl")?;
+        let prefilter_shader = assets.load_shader(device, "shaders/ibl/prefilter.wgsl")?;
+        let brdf_shader = assets.load_shader(device, "shaders/ibl/brdf_lut.wgsl")?;
+
+        let irradiance = create_storage_texture(device, IRRADIANCE_SIZE, 1, true, "irradiance_map");
+        let prefiltered = create_storage_texture(device, PREFILTERED_SIZE, PREFILTERED_MIP_LEVELS, true, "prefiltered_environment_map");
+        let brdf_lut = create_storage_texture(device, BRDF_LUT_SIZE, 1, false, "brdf_lut");
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Environment Encoder"),
+        });
+
+        // Irradiance and prefiltering both read the environment and write a cube map
+        let convolve_kernel = |shader: &Handle<wgpu::ShaderModule>, label: &str| {
+            ComputeBuilder::new(label, shader)
+                .workgroup_size([WORKGROUP_SIZE, WORKGROUP_SIZE, 1])
+                .binding(ComputeBinding::Texture { view_dimension: wgpu::TextureViewDimension::Cube })
+                .binding(ComputeBinding::Sampler)
+                .binding(storage_texture_binding(wgpu::TextureViewDimension::D2Array))
+                .binding(ComputeBinding::Uniform)
+                .build(device, assets)
+        };
+        let irradiance_kernel = convolve_kernel(&irradiance_shader, "Irradiance Pipeline");
+        let prefilter_kernel = convolve_kernel(&prefilter_shader, "Prefilter Pipeline");
+
+        let convolve = |encoder: &mut wgpu::CommandEncoder, kernel: &ComputeKernel, target: &wgpu::Texture, mip_level: u32, size: u32, roughness: f32| {
+            let params = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Prefilter Params Buffer"),
+                contents: bytemuck::cast_slice(&[PrefilterParams { roughness, _padding: [0.0; 3] }]),
+                usage: wgpu::BufferUsage::UNIFORM,
+            });
+            let output = target.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("environment_storage_view"),
+                dimension: Some(wgpu::TextureViewDimension::D2Array),
+                base_mip_level: mip_level,
+                mip_level_count: std::num::NonZeroU32::new(1),
+                ..Default::default()
+            });
+            let bind_group = kernel.bind_group(device, &[
+                wgpu::BindingResource::TextureView(&environment.view),
+                wgpu::BindingResource::Sampler(&environment.sampler),
+                wgpu::BindingResource::TextureView(&output),
+                params.as_entire_binding(),
+            ]);
+            kernel.dispatch(encoder, &bind_group, [size, size, 6]);
+        };
+
+        convolve(&mut encoder, &irradiance_kernel, &irradiance.texture, 0, IRRADIANCE_SIZE, 0.0);
+        for level in 0..PREFILTERED_MIP_LEVELS {
+            let roughness = level as f32 / (PREFILTERED_MIP_LEVELS - 1) as f32;
+            convolve(&mut encoder, &prefilter_kernel, &prefiltered.texture, level, (PREFILTERED_SIZE >> level).max(1), roughness);
+        }
+
+        let brdf_kernel = ComputeBuilder::new("BRDF LUT Pipeline", &brdf_shader)
+            .workgroup_size([WORKGROUP_SIZE, WORKGROUP_SIZE, 1])
+            .binding(storage_texture_binding(wgpu::TextureViewDimension::D2))
+            .build(device, assets);
+        let brdf_bind_group = brdf_kernel.bind_group(device, &[wgpu::BindingResource::TextureView(&brdf_lut.view)]);
+        brdf_kernel.dispatch(&mut encoder, &brdf_bind_group, [BRDF_LUT_SIZE, BRDF_LUT_SIZE, 1]);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&irradiance.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&prefiltered.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&brdf_lut.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&prefiltered.sampler),
+                },
+            ],
+            label: Some("environment_bind_group"),
+        });
+
+        Ok(Self {
+            _irradiance: irradiance,
+            _prefiltered: prefiltered,
+            _brdf_lut: brdf_lut,
+            bind_group,
+        })
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
+
+fn storage_texture_binding(view_dimension: wgpu::TextureViewDimension) -> ComputeBinding {
+    ComputeBinding::StorageTexture {
+        access: wgpu::StorageTextureAccess::WriteOnly,
+        format: Texture::HDR_FORMAT,
+        view_dimension,
+    }
+}
+
+// Square HDR texture written by a compute shader and sampled afterwards, either a cube map or a 2D texture
+fn create_storage_texture(device: &wgpu::Device, size: u32, mip_level_count: u32, cube: bool, label: &str) -> Texture {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: if cube { 6 } else { 1 },
+        },
+        mip_level_count,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: Texture::HDR_FORMAT,
+        usage: wgpu::TextureUsage::STORAGE | wgpu::TextureUsage::SAMPLED,
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(if cube { wgpu::TextureViewDimension::Cube } else { wgpu::TextureViewDimension::D2 }),
+        ..Default::default()
+    });
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
+    Texture { texture, view, sampler }
+}
@@ -0,0 +1,90 @@
+use std::fmt;
+
+use crate::camera::CameraError;
+
+// Everything that can go wrong while setting up the renderer in State::new
+#[derive(Debug)]
+pub enum InitError {
+    // No adapter matched the RendererConfig, the message lists what is available
+    NoAdapter(String),
+    DeviceRequestFailed {
+        adapter: String,
+        source: wgpu::RequestDeviceError,
+    },
+    // The adapter can't present to the window
+    IncompatibleSurface { adapter: String },
+    AssetLoad(anyhow::Error),
+    SingularCameraTransform,
+}
+
+impl fmt::Display for InitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InitError::NoAdapter(message) => write!(f, "{}", message),
+            InitError::DeviceRequestFailed { adapter, .. } => write!(f, "Failed to create a device on {}", adapter),
+            InitError::IncompatibleSurface { adapter } => write!(f, "{} can't present to the window", adapter),
+            InitError::AssetLoad(e) => write!(f, "{}", e),
+            InitError::SingularCameraTransform => write!(f, "The camera transform is not invertible"),
+        }
+    }
+}
+
+impl std::error::Error for InitError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            InitError::DeviceRequestFailed { source, .. } => Some(source),
+            // The asset error's own message is shown by Display, so skip to what caused it
+            InitError::AssetLoad(e) => e.source(),
+            _ => None,
+        }
+    }
+}
+
+impl From<CameraError> for InitError {
+    fn from(error: CameraError) -> Self {
+        match error {
+            CameraError::SingularTransform => InitError::SingularCameraTransform,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::path::Path;
+
+    use anyhow::Context;
+
+    use super::*;
+    use crate::mesh::MeshData;
+
+    #[test]
+    fn camera_errors_convert() {
+        let error = InitError::from(CameraError::SingularTransform);
+        assert!(matches!(error, InitError::SingularCameraTransform));
+        assert_eq!(error.to_string(), "The camera transform is not invertible");
+    }
+
+    #[test]
+    fn asset_errors_keep_their_cause() {
+        let path = Path::new("res/does-not-exist.obj");
+        let error = MeshData::from_obj(path)
+            .with_context(|| format!("Failed to load mesh {}", path.display()))
+            .map_err(InitError::AssetLoad)
+            .err()
+            .unwrap();
+
+        assert_eq!(error.to_string(), "Failed to load mesh res/does-not-exist.obj");
+        assert!(error.source().is_some());
+    }
+
+    #[test]
+    fn device_errors_name_the_adapter() {
+        let error = InitError::DeviceRequestFailed {
+            adapter: "llvmpipe".to_string(),
+            source: wgpu::RequestDeviceError,
+        };
+        assert_eq!(error.to_string(), "Failed to create a device on llvmpipe");
+        assert!(error.source().is_some());
+    }
+}
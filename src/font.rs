@@ -0,0 +1,100 @@
+use anyhow::*;
+
+use crate::texture::Texture;
+
+// The built in font has 8x8 pixel glyphs for ASCII
+pub const GLYPH_SIZE: u32 = 8;
+const COLUMNS: u32 = 16;
+const ROWS: u32 = 8;
+
+// Texture with every printable ASCII character, white where the glyph is set and transparent elsewhere
+pub struct GlyphAtlas {
+    pub texture: Texture,
+}
+
+impl GlyphAtlas {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Self> {
+        let img = image::RgbaImage::from_fn(COLUMNS * GLYPH_SIZE, ROWS * GLYPH_SIZE, |x, y| {
+            let glyph = (y / GLYPH_SIZE) * COLUMNS + x / GLYPH_SIZE;
+            let row = font8x8::legacy::BASIC_LEGACY[glyph as usize][(y % GLYPH_SIZE) as usize];
+            // Bit 0 is the leftmost pixel of the row
+            if row & (1 << (x % GLYPH_SIZE)) != 0 {
+                image::Rgba([255, 255, 255, 255])
+            } else {
+                image::Rgba([255, 255, 255, 0])
+            }
+        });
+        let mut texture = Texture::from_image(device, queue, &image::DynamicImage::ImageRgba8(img), Some("Glyph Atlas"))?;
+        // Text is drawn at whole multiples of the glyph size, filtering would only blur it
+        texture.sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Glyph Atlas Sampler"),
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        Ok(Self { texture })
+    }
+}
+
+// Texture coordinates of a character's glyph as [left, top, right, bottom].
+// Characters outside of ASCII are drawn as '?'.
+pub fn glyph_uv(c: char) -> [f32; 4] {
+    let index = if c.is_ascii() { c as u32 } else { '?' as u32 };
+    let column = (index % COLUMNS) as f32;
+    let row = (index / COLUMNS) as f32;
+    [
+        column / COLUMNS as f32,
+        row / ROWS as f32,
+        (column + 1.0) / COLUMNS as f32,
+        (row + 1.0) / ROWS as f32,
+    ]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlyphQuad {
+    // Top left corner in pixels
+    pub position: [f32; 2],
+    pub size: f32,
+    pub uv: [f32; 4],
+}
+
+// Positions the glyphs of text starting at the top left corner `position`, in pixels.
+// Every glyph is GLYPH_SIZE * scale pixels wide and high, '\n' starts a new line.
+pub fn layout_text(position: [f32; 2], text: &str, scale: f32) -> Vec<GlyphQuad> {
+    let size = GLYPH_SIZE as f32 * scale;
+    let mut cursor = position;
+    let mut quads = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\n' => {
+                cursor = [position[0], cursor[1] + size];
+            }
+            ' ' => cursor[0] += size,
+            c => {
+                quads.push(GlyphQuad { position: cursor, size, uv: glyph_uv(c) });
+                cursor[0] += size;
+            }
+        }
+    }
+    quads
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glyphs_are_laid_out_in_rows() {
+        let quads = layout_text([10.0, 20.0], "ab\nc d", 2.0);
+        let positions: Vec<[f32; 2]> = quads.iter().map(|quad| quad.position).collect();
+        assert_eq!(positions, vec![[10.0, 20.0], [26.0, 20.0], [10.0, 36.0], [42.0, 36.0]]);
+        assert!(quads.iter().all(|quad| quad.size == 16.0));
+    }
+
+    #[test]
+    fn glyph_uvs_index_the_atlas() {
+        // 'A' is 65, so column 1 of row 4
+        assert_eq!(glyph_uv('A'), [1.0 / 16.0, 4.0 / 8.0, 2.0 / 16.0, 5.0 / 8.0]);
+        assert_eq!(glyph_uv('é'), glyph_uv('?'));
+    }
+}
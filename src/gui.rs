@@ -0,0 +1,122 @@
+use std::time::Instant;
+
+use egui_wgpu_backend::{RenderPass, ScreenDescriptor};
+use egui_winit_platform::{Platform, PlatformDescriptor};
+use winit::event::{Event, WindowEvent};
+use winit::window::Window;
+
+use crate::transformation_matrix::TransformationMatrix;
+
+// Immediate mode GUI drawn on top of everything else.
+// Windows are added between begin_frame and render through the egui context, e.g.
+//
+//     gui.begin_frame();
+//     egui::Window::new("Settings").show(&gui.context(), |ui| { ... });
+//     gui.render(&device, &queue, &mut encoder, &frame.view);
+pub struct Gui {
+    platform: Platform,
+    render_pass: RenderPass,
+    start: Instant,
+    physical_size: winit::dpi::PhysicalSize<u32>,
+    scale_factor: f64,
+    // Hidden windows aren't drawn and don't capture any input, toggled with F1
+    pub visible: bool,
+}
+
+impl Gui {
+    pub fn new(window: &Window, device: &wgpu::Device, color_format: wgpu::TextureFormat) -> Self {
+        let physical_size = window.inner_size();
+        let scale_factor = window.scale_factor();
+        let platform = Platform::new(PlatformDescriptor {
+            physical_width: physical_size.width,
+            physical_height: physical_size.height,
+            scale_factor,
+            font_definitions: egui::FontDefinitions::default(),
+            style: egui::Style::default(),
+        });
+
+        Self {
+            platform,
+            render_pass: RenderPass::new(device, color_format),
+            start: Instant::now(),
+            physical_size,
+            scale_factor,
+            visible: true,
+        }
+    }
+
+    // Passes the event on to egui, returns whether the GUI used it so the engine should ignore it
+    pub fn handle_event(&mut self, event: &Event<()>) -> bool {
+        if let Event::WindowEvent { event, .. } = event {
+            match event {
+                WindowEvent::Resized(size) => self.physical_size = *size,
+                WindowEvent::ScaleFactorChanged { scale_factor, new_inner_size } => {
+                    self.scale_factor = *scale_factor;
+                    self.physical_size = **new_inner_size;
+                }
+                _ => {}
+            }
+        }
+
+        self.platform.handle_event(event);
+        self.visible && self.platform.captures_event(event)
+    }
+
+    pub fn begin_frame(&mut self) {
+        self.platform.update_time(self.start.elapsed().as_secs_f64());
+        self.platform.begin_frame();
+    }
+
+    pub fn context(&self) -> egui::CtxRef {
+        self.platform.context()
+    }
+
+    // Ends the frame and draws it in its own pass on top of whatever is already in `target`
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView
+    ) {
+        let (_output, shapes) = self.platform.end_frame();
+        if !self.visible {
+            return;
+        }
+
+        let context = self.platform.context();
+        let paint_jobs = context.tessellate(shapes);
+        let screen_descriptor = ScreenDescriptor {
+            physical_width: self.physical_size.width,
+            physical_height: self.physical_size.height,
+            scale_factor: self.scale_factor as f32,
+        };
+
+        self.render_pass.update_texture(device, queue, &context.texture());
+        self.render_pass.update_user_textures(device, queue);
+        self.render_pass.update_buffers(device, queue, &paint_jobs, &screen_descriptor);
+        self.render_pass.execute(encoder, target, &paint_jobs, &screen_descriptor, None);
+    }
+}
+
+// Position, rotation and scale editor, returns whether anything changed
+pub fn edit_transform(ui: &mut egui::Ui, transform: &mut TransformationMatrix) -> bool {
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label("Position");
+        changed |= ui.add(egui::DragValue::new(&mut transform.position.x).speed(0.01).prefix("x: ")).changed();
+        changed |= ui.add(egui::DragValue::new(&mut transform.position.y).speed(0.01).prefix("y: ")).changed();
+        changed |= ui.add(egui::DragValue::new(&mut transform.position.z).speed(0.01).prefix("z: ")).changed();
+    });
+    ui.horizontal(|ui| {
+        ui.label("Rotation");
+        changed |= ui.drag_angle(&mut transform.pitch.0).changed();
+        changed |= ui.drag_angle(&mut transform.yaw.0).changed();
+        changed |= ui.drag_angle(&mut transform.roll.0).changed();
+    });
+    ui.horizontal(|ui| {
+        ui.label("Scale");
+        changed |= ui.add(egui::DragValue::new(&mut transform.scale).speed(0.01).clamp_range(0.001..=100.0)).changed();
+    });
+    changed
+}
@@ -0,0 +1,274 @@
+use std::sync::Arc;
+
+use anyhow::*;
+
+use crate::asset_manager::{AssetManager, Handle};
+use crate::debug_draw::Color;
+use crate::font::{self, GlyphAtlas};
+use crate::pipeline::{PipelineBuilder, PipelineCache};
+
+pub const TEXT_COLOR: Color = [1.0, 1.0, 1.0, 1.0];
+const SHADOW_COLOR: Color = [0.0, 0.0, 0.0, 0.8];
+
+// Room for this many glyphs before the vertex buffer has to grow
+const INITIAL_CAPACITY: usize = 512;
+const VERTICES_PER_GLYPH: usize = 6;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct HudVertex {
+    // In pixels from the top left corner of the screen
+    pub position: [f32; 2],
+    pub tex_coords: [f32; 2],
+    pub color: Color,
+}
+
+impl HudVertex {
+    pub(crate) fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<HudVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ]
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct HudUniforms {
+    screen_size: [f32; 2],
+    _padding: [f32; 2],
+}
+
+// Immediate mode text overlay. Like DebugDraw, text is added during the frame, uploaded by
+// prepare and has to be added again every frame to stay on screen.
+pub struct Hud {
+    // Kept alive for atlas_bind_group
+    _atlas: GlyphAtlas,
+    vertices: Vec<HudVertex>,
+    vertex_count: u32,
+    vertex_buffer: wgpu::Buffer,
+    // In vertices
+    capacity: usize,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    atlas_bind_group: wgpu::BindGroup,
+    pipeline: Arc<wgpu::RenderPipeline>,
+    // Glyphs are GLYPH_SIZE * scale pixels high
+    pub scale: f32,
+}
+
+impl Hud {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        assets: &mut AssetManager,
+        cache: &mut PipelineCache,
+        color_format: wgpu::TextureFormat
+    ) -> Result<Self> {
+        let shader: Handle<wgpu::ShaderModule> = assets.load_shader(device, "shaders/hud.wgsl")?;
+        let atlas = GlyphAtlas::new(device, queue)?;
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("HUD Uniform Buffer"),
+            size: std::mem::size_of::<HudUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let uniform_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }
+            ],
+            label: Some("hud_uniform_bind_group_layout"),
+        });
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &uniform_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                }
+            ],
+            label: Some("hud_uniform_bind_group"),
+        });
+
+        let atlas_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                        filtering: true,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("hud_atlas_bind_group_layout"),
+        });
+        let atlas_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &atlas_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&atlas.texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&atlas.texture.sampler),
+                },
+            ],
+            label: Some("hud_atlas_bind_group"),
+        });
+
+        let pipeline_layout = Arc::new(
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("HUD Pipeline Layout"),
+                bind_group_layouts: &[&uniform_bind_group_layout, &atlas_bind_group_layout],
+                push_constant_ranges: &[],
+            })
+        );
+        // Drawn in its own pass straight onto the frame, so no depth and no MSAA
+        let pipeline = PipelineBuilder::new("HUD Pipeline", &pipeline_layout, &shader, color_format)
+            .vertex_layout(HudVertex::desc())
+            .cull_mode(None)
+            .blend(Some(wgpu::BlendState::ALPHA_BLENDING))
+            .build(device, assets, cache);
+
+        let capacity = INITIAL_CAPACITY * VERTICES_PER_GLYPH;
+        Ok(Self {
+            _atlas: atlas,
+            vertices: Vec::with_capacity(capacity),
+            vertex_count: 0,
+            vertex_buffer: Self::create_vertex_buffer(device, capacity),
+            capacity,
+            uniform_buffer,
+            uniform_bind_group,
+            atlas_bind_group,
+            pipeline,
+            scale: 2.0,
+        })
+    }
+
+    fn create_vertex_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("HUD Vertex Buffer"),
+            size: (capacity * std::mem::size_of::<HudVertex>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    // White text with its top left corner at `position`, in pixels from the top left of the screen
+    pub fn text(&mut self, position: [f32; 2], text: &str) {
+        self.text_colored(position, text, TEXT_COLOR);
+    }
+
+    pub fn text_colored(&mut self, position: [f32; 2], text: &str, color: Color) {
+        // A drop shadow keeps the text readable on bright backgrounds
+        let shadow = [position[0] + self.scale, position[1] + self.scale];
+        self.push_glyphs(shadow, text, SHADOW_COLOR);
+        self.push_glyphs(position, text, color);
+    }
+
+    fn push_glyphs(&mut self, position: [f32; 2], text: &str, color: Color) {
+        for quad in font::layout_text(position, text, self.scale) {
+            let [x, y] = quad.position;
+            let [u0, v0, u1, v1] = quad.uv;
+            let corner = |dx: f32, dy: f32, u: f32, v: f32| HudVertex {
+                position: [x + dx * quad.size, y + dy * quad.size],
+                tex_coords: [u, v],
+                color,
+            };
+            self.vertices.extend_from_slice(&[
+                corner(0.0, 0.0, u0, v0),
+                corner(0.0, 1.0, u0, v1),
+                corner(1.0, 1.0, u1, v1),
+                corner(0.0, 0.0, u0, v0),
+                corner(1.0, 1.0, u1, v1),
+                corner(1.0, 0.0, u1, v0),
+            ]);
+        }
+    }
+
+    // Uploads everything added since the last call and starts a new batch
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, screen_size: winit::dpi::PhysicalSize<u32>) {
+        if self.vertices.len() > self.capacity {
+            self.capacity = self.vertices.len().next_power_of_two();
+            self.vertex_buffer = Self::create_vertex_buffer(device, self.capacity);
+        }
+        if !self.vertices.is_empty() {
+            queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&self.vertices));
+        }
+        let uniforms = HudUniforms {
+            screen_size: [screen_size.width as f32, screen_size.height as f32],
+            _padding: [0.0; 2],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniforms]));
+
+        self.vertex_count = self.vertices.len() as u32;
+        self.vertices.clear();
+    }
+
+    // Draws the overlay in its own pass on top of whatever is already in `target`
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, target: &wgpu::TextureView) {
+        if self.vertex_count == 0 {
+            return;
+        }
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("HUD Pass"),
+            color_attachments: &[
+                wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    }
+                }
+            ],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.atlas_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.draw(0..self.vertex_count, 0..1);
+    }
+}
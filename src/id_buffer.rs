@@ -0,0 +1,276 @@
+use std::sync::Arc;
+
+use anyhow::*;
+use cgmath::{Matrix4, Vector3};
+use futures::executor::block_on;
+use winit::dpi::PhysicalPosition;
+
+use crate::asset_manager::{AssetManager, Handle};
+use crate::material::Material;
+use crate::pipeline::{DepthSettings, PipelineBuilder, PipelineCache};
+use crate::scene::SceneObject;
+use crate::texture::Texture;
+use crate::vertex::Vertex;
+
+// 0 is cleared into pixels without an object
+pub fn object_id(index: usize) -> u32 {
+    index as u32 + 1
+}
+
+pub fn object_index(id: u32) -> Option<usize> {
+    id.checked_sub(1).map(|index| index as usize)
+}
+
+// Scales and moves the pixel at (x, y) of a width by height window over the whole clip space,
+// so rendering with it applied after the projection draws just that pixel into a 1x1 target
+pub fn pick_matrix(x: u32, y: u32, width: u32, height: u32) -> Matrix4<f32> {
+    let (width, height) = (width as f32, height as f32);
+    let center_x = (x as f32 + 0.5) / width * 2.0 - 1.0;
+    let center_y = 1.0 - (y as f32 + 0.5) / height * 2.0;
+    Matrix4::from_nonuniform_scale(width, height, 1.0)
+        * Matrix4::from_translation(Vector3::new(-center_x, -center_y, 0.0))
+}
+
+// Pixel exact picking: renders the id of the object under the cursor into a 1x1 R32Uint texture
+// and reads it back. Unlike picking::pick it costs the same for any number of triangles and
+// respects alpha cutouts and transparency, but stalls until the GPU has finished.
+// Reuses the objects' uniforms, so object ids have to be written into them with object_id.
+pub struct IdBuffer {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    // Without MSAA, unlike the scene's
+    depth_texture: Texture,
+    // Of the window, the targets are always 1x1
+    width: u32,
+    height: u32,
+    // pick_matrix of the cursor
+    pick_buffer: wgpu::Buffer,
+    pick_bind_group: wgpu::BindGroup,
+    readback: wgpu::Buffer,
+    pipeline: Arc<wgpu::RenderPipeline>,
+}
+
+impl IdBuffer {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Uint;
+
+    // Takes the layouts of Material::create_bind_group_layout and the object uniforms
+    pub fn new(
+        device: &wgpu::Device,
+        assets: &mut AssetManager,
+        cache: &mut PipelineCache,
+        sc_desc: &wgpu::SwapChainDescriptor,
+        material_layout: &wgpu::BindGroupLayout,
+        uniform_layout: &wgpu::BindGroupLayout
+    ) -> Result<Self> {
+        let shader: Handle<wgpu::ShaderModule> = assets.load_shader(device, "shaders/id.wgsl")?;
+        let pick_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Id Buffer Pick Buffer"),
+            size: std::mem::size_of::<[[f32; 4]; 4]>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let pick_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }
+            ],
+            label: Some("id_buffer_pick_bind_group_layout"),
+        });
+        let pick_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &pick_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: pick_buffer.as_entire_binding(),
+                }
+            ],
+            label: Some("id_buffer_pick_bind_group"),
+        });
+        let layout = Arc::new(
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Id Buffer Pipeline Layout"),
+                bind_group_layouts: &[material_layout, uniform_layout, &pick_layout],
+                push_constant_ranges: &[],
+            })
+        );
+        let pipeline = PipelineBuilder::new("Id Buffer Pipeline", &layout, &shader, Self::FORMAT)
+            .vertex_layout(Vertex::desc())
+            // Ids can't be blended
+            .blend(None)
+            .depth(Some(DepthSettings {
+                format: Texture::DEPTH_FORMAT,
+                write_enabled: true,
+                compare: wgpu::CompareFunction::Less,
+            }))
+            .build(device, assets, cache);
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Id Buffer Readback"),
+            size: std::mem::size_of::<u32>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("id_buffer"),
+            size: wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::COPY_SRC,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let depth_desc = wgpu::SwapChainDescriptor {
+            width: 1,
+            height: 1,
+            ..sc_desc.clone()
+        };
+
+        Ok(Self {
+            texture,
+            view,
+            depth_texture: Texture::create_depth_texture(device, &depth_desc, 1, "id_buffer_depth_texture"),
+            width: sc_desc.width,
+            height: sc_desc.height,
+            pick_buffer,
+            pick_bind_group,
+            readback,
+            pipeline,
+        })
+    }
+
+    pub fn resize(&mut self, sc_desc: &wgpu::SwapChainDescriptor) {
+        self.width = sc_desc.width;
+        self.height = sc_desc.height;
+    }
+
+    // Index of the object drawn at a position in the window, in pixels. Only the pixel under the
+    // cursor is rendered, and the call blocks until it's read back.
+    pub fn pick(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        objects: &[SceneObject],
+        materials: &[Material],
+        assets: &AssetManager,
+        cursor: PhysicalPosition<f64>
+    ) -> Option<usize> {
+        if cursor.x < 0.0 || cursor.y < 0.0 {
+            return None;
+        }
+        let (x, y) = (cursor.x as u32, cursor.y as u32);
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let pick: [[f32; 4]; 4] = pick_matrix(x, y, self.width, self.height).into();
+        queue.write_buffer(&self.pick_buffer, 0, bytemuck::cast_slice(&[pick]));
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Id Buffer Encoder"),
+        });
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Id Buffer Pass"),
+                color_attachments: &[
+                    wgpu::RenderPassColorAttachment {
+                        view: &self.view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                            store: true,
+                        }
+                    }
+                ],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth_texture.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(2, &self.pick_bind_group, &[]);
+            for object in objects {
+                let mesh = assets.mesh(&object.mesh);
+                render_pass.set_bind_group(0, &materials[object.material].bind_group, &[]);
+                render_pass.set_bind_group(1, &object.uniform_bind_group, &[]);
+                render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                render_pass.draw_indexed(0..mesh.num_indices, 0, 0..1);
+            }
+        }
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &self.readback,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT),
+                    rows_per_image: std::num::NonZeroU32::new(1),
+                },
+            },
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = self.readback.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        if let Err(e) = block_on(mapping) {
+            log::error!("Failed to read back the id buffer: {:?}", e);
+            return None;
+        }
+        let id = {
+            let data = slice.get_mapped_range();
+            u32::from_ne_bytes([data[0], data[1], data[2], data[3]])
+        };
+        self.readback.unmap();
+        object_index(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_round_trip_and_leave_zero_empty() {
+        assert_eq!(object_index(0), None);
+        for index in [0, 1, 41].iter() {
+            assert_eq!(object_index(object_id(*index)), Some(*index));
+        }
+    }
+
+    #[test]
+    fn pick_matrix_fills_clip_space_with_the_pixel() {
+        use cgmath::{Vector4, assert_abs_diff_eq};
+
+        let pick = pick_matrix(3, 1, 8, 4);
+        // The pixel's corners in clip space, with w = 2 to check the perspective divide
+        let top_left = Vector4::new(2.0 * (3.0 / 4.0 - 1.0), 2.0 * (1.0 - 1.0 / 2.0), 0.5, 2.0);
+        let bottom_right = Vector4::new(2.0 * (4.0 / 4.0 - 1.0), 2.0 * (1.0 - 2.0 / 2.0), 0.5, 2.0);
+        assert_abs_diff_eq!(pick * top_left, Vector4::new(-2.0, 2.0, 0.5, 2.0), epsilon = 1e-5);
+        assert_abs_diff_eq!(pick * bottom_right, Vector4::new(2.0, -2.0, 0.5, 2.0), epsilon = 1e-5);
+    }
+}
@@ -0,0 +1,28 @@
+// Scene wide light, tinting and scaling the image based lighting from the environment
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Light {
+    // Linear RGB
+    pub color: [f32; 3],
+    pub intensity: f32,
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Self {
+            color: [1.0, 1.0, 1.0],
+            intensity: 1.0,
+        }
+    }
+}
+
+impl Light {
+    // Color scaled by intensity, padded to a vec4 for uniform buffers
+    pub fn radiance(&self) -> [f32; 4] {
+        [
+            self.color[0] * self.intensity,
+            self.color[1] * self.intensity,
+            self.color[2] * self.intensity,
+            1.0,
+        ]
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::{HashMap, HashSet};
+
+use cgmath::{InnerSpace, Point3, Vector2, Vector3, Zero};
+
+use crate::asset_manager::Handle;
+use crate::culling::Aabb;
+use crate::mesh::{compute_normals, compute_tangents, Mesh, MeshData};
+use crate::vertex::Vertex;
+
+// Finest grid simplify tries, in cells along the longest side of the mesh
+const MAX_GRID_CELLS: u32 = 1024;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LodMetric {
+    // World space distance between the camera and the object's bounding sphere
+    Distance,
+    // Fraction of the screen height the object's bounding sphere covers, see Camera::screen_size
+    ScreenSize,
+}
+
+// Meshes of decreasing detail for one object, with the thresholds at which they take over
+// from each other. Tracks the level that was used last, so switches can lag behind the
+// thresholds to avoid popping back and forth.
+#[derive(Debug, Clone)]
+pub struct LodGroup {
+    metric: LodMetric,
+    meshes: Vec<Handle<Mesh>>,
+    // thresholds[i] is where meshes[i + 1] takes over from meshes[i]
+    thresholds: Vec<f32>,
+    current: usize,
+}
+
+impl LodGroup {
+    // mesh is the most detailed level, used up close
+    pub fn new(mesh: Handle<Mesh>, metric: LodMetric) -> Self {
+        Self {
+            metric,
+            meshes: vec![mesh],
+            thresholds: Vec::new(),
+            current: 0,
+        }
+    }
+
+    // Adds a coarser level, used beyond threshold distance or below threshold screen size.
+    // Levels have to be added from fine to coarse.
+    pub fn with_level(mut self, mesh: Handle<Mesh>, threshold: f32) -> Self {
+        self.meshes.push(mesh);
+        self.thresholds.push(threshold);
+        self
+    }
+
+    pub fn metric(&self) -> LodMetric {
+        self.metric
+    }
+
+    // The most detailed mesh, whose bounds are used to measure the object
+    pub fn base_mesh(&self) -> &Handle<Mesh> {
+        &self.meshes[0]
+    }
+
+    pub fn level(&self) -> usize {
+        self.current
+    }
+
+    pub fn mesh(&self) -> &Handle<Mesh> {
+        &self.meshes[self.current]
+    }
+
+    // Picks the level for value, measured in the group's metric, and returns its mesh.
+    // hysteresis is the fraction a value has to get past a threshold before the level changes.
+    pub fn select(&mut self, value: f32, hysteresis: f32) -> &Handle<Mesh> {
+        self.current = select_level(self.current, &self.thresholds, self.metric, value, hysteresis);
+        self.mesh()
+    }
+}
+
+fn select_level(current: usize, thresholds: &[f32], metric: LodMetric, value: f32, hysteresis: f32) -> usize {
+    // Distances grow and screen sizes shrink towards the coarser levels
+    let past = |threshold: f32| match metric {
+        LodMetric::Distance => value > threshold * (1.0 + hysteresis),
+        LodMetric::ScreenSize => value < threshold * (1.0 - hysteresis),
+    };
+    let before = |threshold: f32| match metric {
+        LodMetric::Distance => value < threshold * (1.0 - hysteresis),
+        LodMetric::ScreenSize => value > threshold * (1.0 + hysteresis),
+    };
+
+    let mut level = current.min(thresholds.len());
+    while level < thresholds.len() && past(thresholds[level]) {
+        level += 1;
+    }
+    while level > 0 && before(thresholds[level - 1]) {
+        level -= 1;
+    }
+    level
+}
+
+// Lower detail version of a mesh with about ratio times as many triangles, for generating LODs.
+// Uses vertex clustering: vertices are snapped to a grid, the ones sharing a cell are merged and
+// triangles that collapse are dropped. It's fast and never fails, but doesn't keep texture seams
+// or sharp features, which matters little at the distances lower levels are seen from.
+pub fn simplify(data: &MeshData, ratio: f32) -> MeshData {
+    let triangles = data.indices.len() / 3;
+    let target = ((triangles as f32 * ratio).round() as usize).max(1);
+    if target >= triangles {
+        return data.clone();
+    }
+
+    // Finer grids keep more triangles, find the finest one that gets down to the target
+    let (mut low, mut high) = (1, MAX_GRID_CELLS);
+    while low < high {
+        let cells = (low + high).div_ceil(2);
+        if cluster(data, cells).indices.len() / 3 <= target {
+            low = cells;
+        } else {
+            high = cells - 1;
+        }
+    }
+    // Coarse grids can collapse a mesh completely, an empty mesh is no use as a LOD
+    let mut cells = low;
+    loop {
+        let simplified = cluster(data, cells);
+        if !simplified.indices.is_empty() || cells >= MAX_GRID_CELLS {
+            return simplified;
+        }
+        cells += 1;
+    }
+}
+
+// Sums of the attributes of the vertices merged into one, averaged once all are added
+struct Cluster {
+    position: Vector3<f32>,
+    tex_coords: Vector2<f32>,
+    normal: Vector3<f32>,
+    count: f32,
+}
+
+impl Default for Cluster {
+    fn default() -> Self {
+        Self {
+            position: Vector3::zero(),
+            tex_coords: Vector2::zero(),
+            normal: Vector3::zero(),
+            count: 0.0,
+        }
+    }
+}
+
+// Merges the vertices in each cell of a grid with cells along the longest side of the mesh
+fn cluster(data: &MeshData, cells: u32) -> MeshData {
+    let bounds = Aabb::from_points(data.vertices.iter().map(|vertex| Point3::from(vertex.position)));
+    let size = bounds.max - bounds.min;
+    let cell_size = size.x.max(size.y).max(size.z) / cells as f32;
+    if cell_size <= 0.0 {
+        return data.clone();
+    }
+
+    let cell = |position: [f32; 3]| {
+        let offset = (Point3::from(position) - bounds.min) / cell_size;
+        let index = |x: f32| (x.floor() as i32).min(cells as i32 - 1);
+        (index(offset.x), index(offset.y), index(offset.z))
+    };
+
+    let mut cells_to_clusters: HashMap<(i32, i32, i32), usize> = HashMap::new();
+    let mut clusters: Vec<Cluster> = Vec::new();
+    let remap: Vec<u32> = data.vertices.iter()
+        .map(|vertex| {
+            let index = *cells_to_clusters.entry(cell(vertex.position)).or_insert_with(|| {
+                clusters.push(Cluster::default());
+                clusters.len() - 1
+            });
+            let cluster = &mut clusters[index];
+            cluster.position += Vector3::from(vertex.position);
+            cluster.tex_coords += Vector2::from(vertex.tex_coords);
+            cluster.normal += Vector3::from(vertex.normal);
+            cluster.count += 1.0;
+            index as u32
+        })
+        .collect();
+
+    let mut vertices: Vec<Vertex> = clusters.iter()
+        .map(|cluster| Vertex {
+            position: (cluster.position / cluster.count).into(),
+            tex_coords: (cluster.tex_coords / cluster.count).into(),
+            normal: cluster.normal.into(),
+            tangent: [0.0; 4],
+        })
+        .collect();
+
+    let mut indices = Vec::new();
+    let mut seen = HashSet::new();
+    for triangle in data.indices.chunks_exact(3) {
+        let [a, b, c] = [remap[triangle[0] as usize], remap[triangle[1] as usize], remap[triangle[2] as usize]];
+        if a == b || b == c || a == c {
+            continue;
+        }
+        // Rotated to start at the smallest index, so the same triangle is only kept once while
+        // triangles with the opposite winding, like the back of a two sided leaf, stay
+        let key = if a < b && a < c { (a, b, c) } else if b < c { (b, c, a) } else { (c, a, b) };
+        if seen.insert(key) {
+            indices.extend_from_slice(&[a, b, c]);
+        }
+    }
+
+    // Opposite normals merged into the same vertex cancel out, those are rebuilt from the faces
+    let mut face_normals = vertices.clone();
+    compute_normals(&mut face_normals, &indices);
+    for (vertex, fallback) in vertices.iter_mut().zip(face_normals) {
+        let normal = Vector3::from(vertex.normal);
+        vertex.normal = if normal.magnitude2() > 1e-6 { normal.normalize().into() } else { fallback.normal };
+    }
+    compute_tangents(&mut vertices, &indices);
+
+    MeshData { vertices, indices }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(current: usize, metric: LodMetric, value: f32) -> usize {
+        select_level(current, &[10.0, 20.0], metric, value, 0.1)
+    }
+
+    #[test]
+    fn distance_selects_coarser_levels_further_away() {
+        assert_eq!(check(0, LodMetric::Distance, 5.0), 0);
+        assert_eq!(check(0, LodMetric::Distance, 15.0), 1);
+        assert_eq!(check(0, LodMetric::Distance, 50.0), 2);
+        assert_eq!(check(2, LodMetric::Distance, 1.0), 0);
+    }
+
+    #[test]
+    fn hysteresis_keeps_the_current_level_near_thresholds() {
+        // 10.5 is past the threshold but within 10% of it
+        assert_eq!(check(0, LodMetric::Distance, 10.5), 0);
+        assert_eq!(check(1, LodMetric::Distance, 10.5), 1);
+        assert_eq!(check(1, LodMetric::Distance, 9.5), 1);
+        assert_eq!(check(1, LodMetric::Distance, 8.5), 0);
+        assert_eq!(check(0, LodMetric::Distance, 11.5), 1);
+    }
+
+    #[test]
+    fn screen_size_selects_coarser_levels_when_smaller() {
+        let check = |current: usize, value: f32| select_level(current, &[0.5, 0.1], LodMetric::ScreenSize, value, 0.1);
+        assert_eq!(check(0, 0.8), 0);
+        assert_eq!(check(0, 0.3), 1);
+        assert_eq!(check(0, 0.01), 2);
+        assert_eq!(check(1, 0.52), 1);
+        assert_eq!(check(1, 0.6), 0);
+    }
+
+    // Flat grid on the xy plane with cells * cells quads
+    fn grid(cells: u32) -> MeshData {
+        let mut vertices = Vec::new();
+        for y in 0..=cells {
+            for x in 0..=cells {
+                let (s, t) = (x as f32 / cells as f32, y as f32 / cells as f32);
+                vertices.push(Vertex { position: [s, t, 0.0], tex_coords: [s, 1.0 - t], normal: [0.0, 0.0, 1.0], tangent: [0.0; 4] });
+            }
+        }
+        let mut indices = Vec::new();
+        for y in 0..cells {
+            for x in 0..cells {
+                let corner = y * (cells + 1) + x;
+                indices.extend_from_slice(&[corner, corner + 1, corner + cells + 2, corner, corner + cells + 2, corner + cells + 1]);
+            }
+        }
+        MeshData { vertices, indices }
+    }
+
+    #[test]
+    fn simplify_reduces_triangles_and_keeps_the_shape() {
+        let source = grid(32);
+        let simplified = simplify(&source, 0.25);
+        let triangles = simplified.indices.len() / 3;
+        assert!(triangles > 0 && triangles <= source.indices.len() / 3 / 4, "{} triangles", triangles);
+        assert!(simplified.indices.iter().all(|index| (*index as usize) < simplified.vertices.len()));
+
+        let bounds = Aabb::from_points(simplified.vertices.iter().map(|vertex| Point3::from(vertex.position)));
+        assert!(bounds.min.x < 0.1 && bounds.min.y < 0.1 && bounds.max.x > 0.9 && bounds.max.y > 0.9);
+        for vertex in &simplified.vertices {
+            assert!((Vector3::from(vertex.normal) - Vector3::unit_z()).magnitude() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn simplify_never_returns_an_empty_mesh() {
+        let source = grid(4);
+        let simplified = simplify(&source, 0.0);
+        assert!(!simplified.indices.is_empty());
+        assert_eq!(simplify(&source, 1.0).indices, source.indices);
+    }
+}
@@ -0,0 +1,97 @@
+use std::convert::TryInto;
+
+use anyhow::*;
+use wgpu::util::DeviceExt;
+
+use crate::asset_manager::AssetManager;
+use crate::compute::{self, ComputeBinding, ComputeBuilder, ComputeKernel};
+
+// Middle grey, what the scene's average luminance is exposed to
+const KEY_VALUE: f32 = 0.18;
+// Keeps a black scene from being blown out, the Exposure slider's maximum
+const MAX_EXPOSURE: f32 = 8.0;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct LuminanceParams {
+    width: u32,
+    height: u32,
+}
+
+// Log average luminance of pixel_count pixels, from the sums of their logs per row
+pub fn average_log_luminance(row_sums: &[f32], pixel_count: u32) -> f32 {
+    row_sums.iter().sum::<f32>() / pixel_count as f32
+}
+
+// Exposure that maps a scene with this log average luminance to middle grey
+pub fn exposure(average_log_luminance: f32) -> f32 {
+    (KEY_VALUE / average_log_luminance.exp()).min(MAX_EXPOSURE)
+}
+
+// Measures the average brightness of the HDR scene on the GPU to pick an exposure. Blocks until
+// the GPU has finished, so it runs when asked to rather than every frame.
+pub struct LuminanceMeter {
+    kernel: ComputeKernel,
+}
+
+impl LuminanceMeter {
+    pub fn new(device: &wgpu::Device, assets: &mut AssetManager) -> Result<Self> {
+        let shader = assets.load_shader(device, "shaders/compute/luminance.wgsl")?;
+        let kernel = ComputeBuilder::new("Luminance", &shader)
+            .workgroup_size([64, 1, 1])
+            .binding(ComputeBinding::Uniform)
+            .binding(ComputeBinding::Texture { view_dimension: wgpu::TextureViewDimension::D2 })
+            .binding(ComputeBinding::Storage { read_only: false })
+            .build(device, assets);
+        Ok(Self { kernel })
+    }
+
+    // Exposure for what has been submitted into scene_view so far, which is width by height
+    pub fn measure(&self, device: &wgpu::Device, queue: &wgpu::Queue, scene_view: &wgpu::TextureView, width: u32, height: u32) -> Result<f32> {
+        if width == 0 || height == 0 {
+            bail!("Can't measure the luminance of an empty scene");
+        }
+        let params = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Luminance Params"),
+            contents: bytemuck::bytes_of(&LuminanceParams { width, height }),
+            usage: wgpu::BufferUsage::UNIFORM,
+        });
+        let size = height as usize * std::mem::size_of::<f32>();
+        let rows = compute::create_storage_buffer(device, &vec![0; size], "Luminance Rows");
+        let bind_group = self.kernel.bind_group(device, &[
+            params.as_entire_binding(),
+            wgpu::BindingResource::TextureView(scene_view),
+            rows.as_entire_binding(),
+        ]);
+        self.kernel.run(device, queue, &bind_group, [height, 1, 1]);
+
+        let bytes = compute::read_buffer(device, queue, &rows, size as wgpu::BufferAddress)?;
+        // The readback isn't necessarily aligned for a cast to f32
+        let row_sums: Vec<f32> = bytes.chunks_exact(4)
+            .map(|chunk| f32::from_ne_bytes(chunk.try_into().unwrap()))
+            .collect();
+        Ok(exposure(average_log_luminance(&row_sums, width * height)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-5;
+
+    #[test]
+    fn averages_the_logs_of_every_pixel() {
+        // Two rows of two pixels, half of them at luminance 1 and half at 0.25
+        let row_sums = [0.0, 2.0 * 0.25f32.ln()];
+        let average = average_log_luminance(&row_sums, 4);
+        assert!((average.exp() - 0.5).abs() < EPSILON);
+    }
+
+    #[test]
+    fn exposes_the_average_to_middle_grey() {
+        assert!((exposure(KEY_VALUE.ln()) - 1.0).abs() < EPSILON);
+        assert!((exposure(0.36f32.ln()) - 0.5).abs() < EPSILON);
+        assert_eq!(exposure(f32::MIN), MAX_EXPOSURE);
+    }
+}
@@ -5,19 +5,79 @@ use winit::{
     window::WindowBuilder,
 };
 use futures::executor::block_on;
+use anyhow::Context;
 use wgpu::util::DeviceExt;
-use wgpu::{BindGroup, BindGroupLayout, Buffer, Device, Face};
+use wgpu::{BindGroup, BindGroupLayout, Buffer, Device};
+use std::sync::Arc;
 use crate::texture::Texture;
-use cgmath::{Deg, Matrix4, Point3, Vector3};
+use cgmath::{Deg, EuclideanSpace, InnerSpace, Matrix4, Point3, SquareMatrix, Vector3, Zero};
+use std::collections::HashSet;
+use std::time::Duration;
+use crate::timing::GameClock;
 use crate::camera::{Camera};
 use crate::transformation_matrix::TransformationMatrix;
+use crate::asset_manager::{AssetManager, Handle};
+use crate::mesh::{Mesh, MeshData};
+use crate::pipeline::{PipelineBuilder, PipelineCache};
+use crate::material::{AlphaMode, Material, MaterialPipelines};
+use crate::scene::{ObjectUniforms, SceneObject};
+use bytemuck::Zeroable;
+use crate::config::RendererConfig;
+use crate::error::InitError;
+use crate::profiler::Profiler;
+use crate::debug_draw::DebugDraw;
+use crate::hud::Hud;
+use crate::gui::Gui;
+use crate::light::Light;
+use crate::capture::FrameCapture;
+use crate::skybox::Skybox;
+use crate::environment::Environment;
+use crate::culling::Frustum;
+use crate::lod::{LodGroup, LodMetric};
+use crate::picking::{Hit, PickingMode, Ray};
+use crate::id_buffer::IdBuffer;
+use crate::render_mode::{RenderMode, RenderModes};
+use crate::luminance::LuminanceMeter;
+use crate::post_process::{HeatMapParams, PostProcessChain, TonemapOperator, TonemapParams};
 
 mod vertex;
 mod texture;
 mod camera;
 mod transformation_matrix;
+mod mesh;
+mod asset_manager;
+mod asset_loader;
+mod pipeline;
+mod material;
+mod scene;
+mod config;
+mod error;
+mod timing;
+mod profiler;
+mod debug_draw;
+mod font;
+mod hud;
+mod gui;
+mod light;
+mod capture;
+mod post_process;
+mod bloom;
+mod skybox;
+mod environment;
+mod culling;
+mod lod;
+mod picking;
+mod id_buffer;
+mod render_mode;
+mod compute;
+mod luminance;
 
 
+const FIXED_TIMESTEP: Duration = Duration::from_micros(16_667);
+// In units per second
+const CAMERA_SPEED: f32 = 1.0;
+// Size of the cube map faces a skybox panorama is projected onto
+const SKYBOX_FACE_SIZE: u32 = 512;
 
 struct State {
     surface: wgpu::Surface,
@@ -26,55 +86,102 @@ struct State {
     sc_desc: wgpu::SwapChainDescriptor,
     swap_chain: wgpu::SwapChain,
     size: winit::dpi::PhysicalSize<u32>,
-    render_pipeline: wgpu::RenderPipeline,
-    vertex_buffer: wgpu::Buffer,
-    index_buffer: wgpu::Buffer,
-    num_vertices: u32,
-    num_indices: u32,
-    diffuse_bind_group: wgpu::BindGroup,
-    diffuse_texture: texture::Texture,
+    render_pipeline_layout: Arc<wgpu::PipelineLayout>,
+    pipeline_cache: PipelineCache,
+    shader: Handle<wgpu::ShaderModule>,
+    material_pipelines: MaterialPipelines,
+    depth_texture: Texture,
+    sample_count: u32,
+    // Only used when sample_count is above 1
+    multisampled_framebuffer: Option<wgpu::TextureView>,
+    assets: AssetManager,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    material_bind_group_layout: wgpu::BindGroupLayout,
+    materials: Vec<Material>,
+    objects: Vec<SceneObject>,
     camera: camera::Camera,
-    uniform_buffer: wgpu::Buffer,
-    uniform_bind_group: wgpu::BindGroup,
+    // Camera as of the previous fixed update, for interpolation
+    previous_camera: camera::Camera,
+    // Interpolated camera the last frame was drawn with, which picking has to match
+    render_camera: camera::Camera,
+    held_keys: HashSet<VirtualKeyCode>,
+    pub clock: GameClock,
+    // Only when profiling is enabled in the config
+    pub profiler: Option<Profiler>,
+    pub debug_draw: DebugDraw,
+    // Draw the grid and object axes, toggled with G
+    show_debug_gizmos: bool,
+    frustum_culling: bool,
+    // Keep culling against culling_view_projection while the camera moves, to see what it culls
+    freeze_culling: bool,
+    culling_view_projection: Matrix4<f32>,
+    // See LodGroup::select
+    lod_hysteresis: f32,
+    // Last known position of the cursor in the window, in pixels
+    cursor_position: winit::dpi::PhysicalPosition<f64>,
+    render_mode: RenderMode,
+    render_modes: RenderModes,
+    picking_mode: PickingMode,
+    id_buffer: IdBuffer,
+    // Index of the object selected with the last left click
+    selected_object: Option<usize>,
+    // Where the click hit the object, only known when ray casting
+    selection_hit: Option<Hit>,
+    pub hud: Hud,
+    // Frame statistics overlay, toggled with H
+    show_stats: bool,
+    // Drawn behind the scene, clear_color shows through without one
+    skybox: Option<Skybox>,
+    environment: Environment,
+    clear_color: [f32; 3],
+    gui: Gui,
+    light: Light,
+    // The scene is rendered into the chain in HDR, which writes the result to frame_texture
+    pub post_process: PostProcessChain,
+    luminance: LuminanceMeter,
+    // Set by the Auto exposure button, the next shaded frame is measured before post processing
+    measure_exposure: bool,
+    // Everything is rendered here and then copied to the swap chain, since swap chain
+    // textures can't be read back for captures
+    frame_texture: Texture,
+    frame_bind_group: wgpu::BindGroup,
+    blit_pipeline: Arc<wgpu::RenderPipeline>,
+    // Screenshots with F12, recording with F11
+    pub capture: FrameCapture,
 }
 
 
 impl State {
-    async fn new(window: &Window)  -> Self {
+    async fn new(window: &Window, config: &RendererConfig) -> Result<Self, InitError> {
         let size = window.inner_size();
 
 
 
         // The instance is a handle to our GPU
         // BackendBit::PRIMARY => Vulkan + Metal + DX12 + Browser WebGPU
-        let instance = wgpu::Instance::new(wgpu::BackendBit::VULKAN);
+        let instance = wgpu::Instance::new(config.backends);
         let surface = unsafe { instance.create_surface(window) };
-        let adapter = instance.request_adapter(
-            &wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
-            },
-        ).await.unwrap();
+        let adapter = config::select_adapter(&instance, &surface, config).await?;
+        let adapter_info = adapter.get_info();
+        log::info!("Using adapter {} ({:?})", adapter_info.name, adapter_info.backend);
 
+        let features = if config.profile {
+            Profiler::required_features(&adapter)
+        } else {
+            wgpu::Features::empty()
+        } | RenderModes::optional_features(&adapter);
         let (device, queue) = adapter.request_device(
             &wgpu::DeviceDescriptor {
-                features: wgpu::Features::empty(),
+                features,
                 limits: wgpu::Limits::default(),
                 label: None,
             },
             None, // Trace path
-        ).await.unwrap();
+        ).await.map_err(|source| InitError::DeviceRequestFailed { adapter: adapter_info.name.clone(), source })?;
 
 
         let aspect = size.width as f32 / size.height as f32;
 
-        let translation =  Vector3 {
-            x: 0.0, y: 0.0, z: 0.0,
-        };
-        let model_transform = TransformationMatrix::new(translation, Deg(0.0), Deg(0.0), Deg(0.0));
-        println!("model: {:?}", model_transform);
-
-
         let camera_translation = Vector3 {
             x: 0.0, y: 0.0, z: 2.0,
         };
@@ -85,142 +192,275 @@ impl State {
         let projection = cgmath::perspective(Deg(90.0), aspect, 0.1, 10.0);
 
         let camera = Camera {
-            model_transform,
             camera_transform,
             projection,
         };
+        // Fail early rather than on the first frame
+        camera.build_view_projection_matrix()?;
 
-        let mvp = camera.build_model_view_projection_matrix();
-        let mvp = &mvp;
-
-        let uniform_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Uniform Buffer"),
-                // contents: bytemuck::cast_slice(&[uniforms]),
-                contents: bytemuck::cast_slice(mvp),
-                usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
-            }
-        );
-
-
-        let (uniform_bind_group_layout, uniform_bind_group) = Self::create_uniform_bind_group(&device, &uniform_buffer);
+        let uniform_bind_group_layout = Self::create_uniform_bind_group_layout(&device);
 
 
         let sc_desc = wgpu::SwapChainDescriptor {
             usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
-            format: adapter.get_swap_chain_preferred_format(&surface).unwrap(),
+            // Tonemapping outputs linear colors, the sRGB format applies the transfer function
+            format: adapter.get_swap_chain_preferred_format(&surface)
+                .map(Texture::srgb_format)
+                .ok_or_else(|| InitError::IncompatibleSurface { adapter: adapter_info.name.clone() })?,
             width: size.width,
             height: size.height,
-            present_mode: wgpu::PresentMode::Fifo,
+            present_mode: config.present_mode,
         };
         let swap_chain = device.create_swap_chain(&surface, &sc_desc);
+        let sample_count = config.sample_count;
+        let depth_texture = Texture::create_depth_texture(&device, &sc_desc, sample_count, "depth_texture");
+        let multisampled_framebuffer = Self::create_multisampled_framebuffer(&device, &sc_desc, sample_count);
+        let frame_texture = Texture::create_frame_texture(&device, &sc_desc, sc_desc.format, "frame_texture");
 
 
-        let diffuse_bytes = include_bytes!("../res/happy-tree.png"); // CHANGED!
-        let diffuse_texture = texture::Texture::from_bytes(&device, &queue, diffuse_bytes, "happy-tree.png").unwrap(); // CHANGED!
+        // Textures missing from the root are replaced by a checkerboard
+        let mut assets = AssetManager::new(asset_manager::default_root());
 
-        let (texture_bind_group_layout, diffuse_bind_group) = State::create_texture_bind_group(&device, &diffuse_texture);
+        // Drawn with a placeholder until the image has been decoded in the background
+        let diffuse_texture = assets.load_texture_async(&device, &queue, "res/happy-tree.png");
 
+        let normal_map = match &config.normal_map {
+            Some(path) => assets.load_normal_map(&device, &queue, config::working_dir_path(path)),
+            None => assets.add_texture(Texture::flat_normal_map(&device, &queue)),
+        };
 
-        // let vs_module = device.create_shader_module(&wgpu::include_spirv!("../shaders/shader.vert.spv"));
-        // let fs_module = device.create_shader_module(&wgpu::include_spirv!("../shaders/shader.frag.spv"));
-        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
-            label: Some("Render Pipeline Layout"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/shader.wgsl").into()),
-            flags: wgpu::ShaderFlags::all(),
-        });
+        let texture_bind_group_layout = State::create_texture_bind_group_layout(&device);
+        let material_bind_group_layout = Material::create_bind_group_layout(&device);
+        let material_bind_group = |assets: &AssetManager| Material::create_bind_group(&device, &material_bind_group_layout, assets.texture(&diffuse_texture), assets.texture(&normal_map));
+        let mut materials = vec![
+            // The happy tree has a transparent background
+            Material {
+                texture: diffuse_texture.clone(),
+                normal_map: normal_map.clone(),
+                normal_scale: 1.0,
+                alpha_mode: AlphaMode::Mask { cutoff: 0.5 },
+                metallic: 0.0,
+                roughness: 0.8,
+                bind_group: material_bind_group(&assets),
+            },
+            Material {
+                texture: diffuse_texture.clone(),
+                normal_map: normal_map.clone(),
+                normal_scale: 1.0,
+                alpha_mode: AlphaMode::Blend,
+                metallic: 0.0,
+                roughness: 0.8,
+                bind_group: material_bind_group(&assets),
+            },
+        ];
 
-        let vertex_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Vertex Buffer"),
-                contents: bytemuck::cast_slice(vertex::VERTICES),
-                usage: wgpu::BufferUsage::VERTEX,
-            }
-        );
 
-        let index_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Index Buffer"),
-                contents: bytemuck::cast_slice(vertex::INDICES),
-                usage: wgpu::BufferUsage::INDEX,
-            }
-        );
+        let shader = assets.load_shader(&device, "shaders/shader.wgsl").map_err(InitError::AssetLoad)?;
 
-        let num_vertices = vertex::VERTICES.len() as u32;
-        let num_indices = vertex::INDICES.len() as u32;
+        let mesh = assets.add_mesh(Mesh::new(&device, vertex::VERTICES, vertex::INDICES, "Pentagon"));
+        // Lower levels of detail for when the trees are further away or cover less of the screen,
+        // distances are in world units and screen sizes fractions of the screen height
+        let pentagon = MeshData { vertices: vertex::VERTICES.to_vec(), indices: vertex::INDICES.to_vec() };
+        let thresholds = match config.lod_metric {
+            LodMetric::Distance => [3.0, 6.0],
+            LodMetric::ScreenSize => [0.2, 0.1],
+        };
+        let lod = LodGroup::new(mesh.clone(), config.lod_metric)
+            .with_level(assets.add_mesh(Mesh::from_data(&device, &lod::simplify(&pentagon, 0.5), "Pentagon LOD 1")), thresholds[0])
+            .with_level(assets.add_mesh(Mesh::from_data(&device, &lod::simplify(&pentagon, 0.2), "Pentagon LOD 2")), thresholds[1]);
 
-        let render_pipeline_layout =
+        let mut objects = Vec::new();
+        let placements = [
+            ("cutout tree", Vector3::new(0.0, 0.0, 0.0), 0),
+            ("transparent tree behind", Vector3::new(-0.3, 0.1, -0.5), 1),
+            ("transparent tree in front", Vector3::new(0.3, -0.1, 0.5), 1),
+        ];
+        for (name, translation, material) in placements.iter() {
+            let transform = TransformationMatrix::new(*translation, Deg(0.0), Deg(0.0), Deg(0.0));
+            let mut object = State::create_object(&device, &uniform_bind_group_layout, name, transform, mesh.clone(), *material);
+            object.lod = Some(lod.clone());
+            objects.push(object);
+        }
+        // Parsed in the background, drawn as a cube until then
+        if let Some(path) = &config.model {
+            let white = assets.add_texture(Texture::solid_color(&device, &queue, [255, 255, 255, 255], "white"));
+            let flat = assets.add_texture(Texture::flat_normal_map(&device, &queue));
+            let bind_group = Material::create_bind_group(&device, &material_bind_group_layout, assets.texture(&white), assets.texture(&flat));
+            materials.push(Material {
+                texture: white,
+                normal_map: flat,
+                normal_scale: 1.0,
+                alpha_mode: AlphaMode::Opaque,
+                metallic: 0.0,
+                roughness: 0.5,
+                bind_group,
+            });
+            let transform = TransformationMatrix::new(Vector3::new(1.0, 0.0, 0.0), Deg(0.0), Deg(0.0), Deg(0.0));
+            let mesh = assets.load_mesh_async(&device, config::working_dir_path(path));
+            objects.push(State::create_object(&device, &uniform_bind_group_layout, "model", transform, mesh, materials.len() - 1));
+        }
+
+        let environment_bind_group_layout = Environment::create_bind_group_layout(&device);
+        let render_pipeline_layout = Arc::new(
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[&texture_bind_group_layout, &uniform_bind_group_layout],
+                bind_group_layouts: &[&material_bind_group_layout, &uniform_bind_group_layout, &environment_bind_group_layout],
                 push_constant_ranges: &[],
-            });
+            })
+        );
 
+        let profiler = if config.profile {
+            Some(Profiler::new(&device, &queue, config.trace_file.is_some()))
+        } else {
+            None
+        };
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "main", // 1.
-                buffers: &[
-                    vertex::Vertex::desc(),
-                ],
-            },
-            fragment: Some(wgpu::FragmentState { // 3.
-                module: &shader,
-                entry_point: "main",
-                targets: &[wgpu::ColorTargetState { // 4.
-                    format: sc_desc.format,
-                    write_mask: wgpu::ColorWrite::ALL,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                }],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList, // 1.
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw, // 2.
-                cull_mode: Some(Face::Back),
-                // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-                polygon_mode: wgpu::PolygonMode::Fill,
-                clamp_depth: false,
-                conservative: false,
-            },
-            depth_stencil: None, // 1.
-            multisample: wgpu::MultisampleState {
-                count: 1, // 2.
-                mask: !0, // 3.
-                alpha_to_coverage_enabled: false, // 4.
-            },
-        });
+        let mut pipeline_cache = PipelineCache::new();
+        let material_pipelines = MaterialPipelines::build(&device, &assets, &mut pipeline_cache, &render_pipeline_layout, &shader, Texture::HDR_FORMAT, sample_count);
+        let debug_draw = DebugDraw::new(&device, &mut assets, &mut pipeline_cache, Texture::HDR_FORMAT, sample_count).map_err(InitError::AssetLoad)?;
+        let render_modes = RenderModes::new(&device, &mut assets, &mut pipeline_cache, &material_bind_group_layout, &uniform_bind_group_layout, sample_count).map_err(InitError::AssetLoad)?;
+        let id_buffer = IdBuffer::new(&device, &mut assets, &mut pipeline_cache, &sc_desc, &material_bind_group_layout, &uniform_bind_group_layout).map_err(InitError::AssetLoad)?;
+        let mut post_process = PostProcessChain::new(&device, &queue, &mut assets, &mut pipeline_cache, &sc_desc, sc_desc.format).map_err(InitError::AssetLoad)?;
+        if let Some(tonemap) = post_process.effect_mut(post_process::TONEMAP) {
+            tonemap.set_params(TonemapParams::new(config.tonemap, config.exposure));
+        }
+        if let Some(path) = &config.lut {
+            let strip = image::open(path)
+                .with_context(|| format!("Failed to load lookup table {}", path.display()))
+                .map_err(InitError::AssetLoad)?;
+            post_process.load_lut(&device, &queue, &mut assets, &mut pipeline_cache, &strip).map_err(InitError::AssetLoad)?;
+        }
+        let luminance = LuminanceMeter::new(&device, &mut assets).map_err(InitError::AssetLoad)?;
+        let hud = Hud::new(&device, &queue, &mut assets, &mut pipeline_cache, sc_desc.format).map_err(InitError::AssetLoad)?;
+        // The skybox also lights the scene, without one the light comes evenly from everywhere
+        let (environment, skybox) = match &config.skybox {
+            Some(path) => {
+                let cube = if path.is_dir() {
+                    Texture::cube_from_dir(&device, &queue, path)
+                } else {
+                    Texture::cube_from_equirectangular(&device, &queue, path, SKYBOX_FACE_SIZE)
+                }.map_err(InitError::AssetLoad)?;
+                let environment = Environment::from_cube(&device, &queue, &mut assets, &environment_bind_group_layout, &cube).map_err(InitError::AssetLoad)?;
+                let skybox = Skybox::new(&device, &mut assets, &mut pipeline_cache, cube, Texture::HDR_FORMAT, sample_count).map_err(InitError::AssetLoad)?;
+                (environment, Some(skybox))
+            }
+            None => {
+                let environment = Environment::uniform(&device, &queue, &mut assets, &environment_bind_group_layout, [1.0, 1.0, 1.0]).map_err(InitError::AssetLoad)?;
+                (environment, None)
+            }
+        };
+        let gui = Gui::new(window, &device, sc_desc.format);
 
-        Self {
+        let blit_shader = assets.load_shader(&device, "shaders/blit.wgsl").map_err(InitError::AssetLoad)?;
+        let blit_pipeline_layout = Arc::new(
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Blit Pipeline Layout"),
+                bind_group_layouts: &[&texture_bind_group_layout],
+                push_constant_ranges: &[],
+            })
+        );
+        let blit_pipeline = PipelineBuilder::new("Blit Pipeline", &blit_pipeline_layout, &blit_shader, sc_desc.format)
+            .cull_mode(None)
+            .build(&device, &assets, &mut pipeline_cache);
+        let frame_bind_group = State::create_texture_bind_group(&device, &texture_bind_group_layout, &frame_texture);
+
+        let mut capture = FrameCapture::new("screenshots");
+        if let Some(dir) = &config.record {
+            capture.start_recording(dir);
+        }
+
+        Ok(Self {
             surface,
             device,
             queue,
             sc_desc,
             swap_chain,
             size,
-            render_pipeline,
-            vertex_buffer,
-            index_buffer,
-            num_vertices,
-            num_indices,
-            diffuse_bind_group,
-            diffuse_texture,
+            render_pipeline_layout,
+            pipeline_cache,
+            shader,
+            material_pipelines,
+            depth_texture,
+            sample_count,
+            multisampled_framebuffer,
+            assets,
+            texture_bind_group_layout,
+            material_bind_group_layout,
+            materials,
+            objects,
+            previous_camera: camera.clone(),
+            render_camera: camera.clone(),
             camera,
+            held_keys: HashSet::new(),
+            clock: GameClock::new(FIXED_TIMESTEP),
+            profiler,
+            debug_draw,
+            show_debug_gizmos: true,
+            frustum_culling: true,
+            freeze_culling: false,
+            // Replaced by the camera's on the first frame
+            culling_view_projection: Matrix4::identity(),
+            lod_hysteresis: 0.1,
+            cursor_position: winit::dpi::PhysicalPosition::new(0.0, 0.0),
+            render_mode: RenderMode::Shaded,
+            render_modes,
+            picking_mode: PickingMode::Ray,
+            id_buffer,
+            selected_object: None,
+            selection_hit: None,
+            hud,
+            show_stats: true,
+            skybox,
+            environment,
+            clear_color: config.clear_color,
+            gui,
+            light: Light::default(),
+            post_process,
+            luminance,
+            measure_exposure: false,
+            frame_texture,
+            frame_bind_group,
+            blit_pipeline,
+            capture,
+        })
+
+    }
+
+    fn create_object(
+        device: &Device,
+        uniform_bind_group_layout: &BindGroupLayout,
+        name: &str,
+        transform: TransformationMatrix,
+        mesh: Handle<Mesh>,
+        material: usize
+    ) -> SceneObject {
+        // Filled in every frame before drawing
+        let uniform_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{} Uniform Buffer", name)),
+                contents: bytemuck::cast_slice(&[ObjectUniforms::zeroed()]),
+                usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            }
+        );
+        let uniform_bind_group = Self::create_uniform_bind_group(device, uniform_bind_group_layout, &uniform_buffer);
+
+        SceneObject {
+            name: name.to_string(),
+            transform,
+            mesh,
+            lod: None,
+            material,
             uniform_buffer,
             uniform_bind_group,
         }
-
     }
 
-    fn create_uniform_bind_group(device: &Device, uniform_buffer: &Buffer) -> (BindGroupLayout, BindGroup) {
-        let uniform_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+    fn create_uniform_bind_group_layout(device: &Device) -> BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &[
                 wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStage::VERTEX,
+                    visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
@@ -230,10 +470,12 @@ impl State {
                 }
             ],
             label: Some("uniform_bind_group_layout"),
-        });
+        })
+    }
 
-        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &uniform_bind_group_layout,
+    fn create_uniform_bind_group(device: &Device, uniform_bind_group_layout: &BindGroupLayout, uniform_buffer: &Buffer) -> BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: uniform_bind_group_layout,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
@@ -241,13 +483,11 @@ impl State {
                 }
             ],
             label: Some("uniform_bind_group"),
-        });
-
-        return (uniform_bind_group_layout, uniform_bind_group)
+        })
     }
 
-    fn create_texture_bind_group(device: &Device, diffuse_texture: &Texture) -> (BindGroupLayout, BindGroup) {
-        let texture_bind_group_layout = device.create_bind_group_layout(
+    fn create_texture_bind_group_layout(device: &Device) -> BindGroupLayout {
+        device.create_bind_group_layout(
             &wgpu::BindGroupLayoutDescriptor {
                 entries: &[
                     wgpu::BindGroupLayoutEntry {
@@ -272,11 +512,13 @@ impl State {
                 ],
                 label: Some("texture_bind_group_layout"),
             }
-        );
+        )
+    }
 
-        let diffuse_bind_group = device.create_bind_group(
+    fn create_texture_bind_group(device: &Device, texture_bind_group_layout: &BindGroupLayout, diffuse_texture: &Texture) -> BindGroup {
+        device.create_bind_group(
             &wgpu::BindGroupDescriptor {
-                layout: &texture_bind_group_layout,
+                layout: texture_bind_group_layout,
                 entries: &[
                     wgpu::BindGroupEntry {
                         binding: 0,
@@ -289,8 +531,7 @@ impl State {
                 ],
                 label: Some("diffuse_bind_group"),
             }
-        );
-        (texture_bind_group_layout, diffuse_bind_group)
+        )
     }
 
     fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
@@ -298,13 +539,468 @@ impl State {
         self.sc_desc.width = new_size.width;
         self.sc_desc.height = new_size.height;
         self.swap_chain = self.device.create_swap_chain(&self.surface, &self.sc_desc);
+        self.depth_texture = Texture::create_depth_texture(&self.device, &self.sc_desc, self.sample_count, "depth_texture");
+        self.multisampled_framebuffer = Self::create_multisampled_framebuffer(&self.device, &self.sc_desc, self.sample_count);
+        self.post_process.resize(&self.device, &self.sc_desc);
+        self.id_buffer.resize(&self.sc_desc);
+        self.frame_texture = Texture::create_frame_texture(&self.device, &self.sc_desc, self.sc_desc.format, "frame_texture");
+        self.frame_bind_group = State::create_texture_bind_group(&self.device, &self.texture_bind_group_layout, &self.frame_texture);
+    }
+
+    fn create_multisampled_framebuffer(device: &Device, sc_desc: &wgpu::SwapChainDescriptor, sample_count: u32) -> Option<wgpu::TextureView> {
+        if sample_count > 1 {
+            Some(Texture::create_multisampled_framebuffer(device, sc_desc, Texture::HDR_FORMAT, sample_count))
+        } else {
+            None
+        }
+    }
+
+    fn set_sample_count(&mut self, sample_count: u32) {
+        self.sample_count = sample_count;
+        self.material_pipelines = MaterialPipelines::build(&self.device, &self.assets, &mut self.pipeline_cache, &self.render_pipeline_layout, &self.shader, Texture::HDR_FORMAT, sample_count);
+        self.debug_draw.rebuild_pipeline(&self.device, &self.assets, &mut self.pipeline_cache, Texture::HDR_FORMAT, sample_count);
+        self.render_modes.rebuild_pipelines(&self.device, &self.assets, &mut self.pipeline_cache, sample_count);
+        if let Some(skybox) = &mut self.skybox {
+            skybox.rebuild_pipeline(&self.device, &self.assets, &mut self.pipeline_cache, Texture::HDR_FORMAT, sample_count);
+        }
+        self.depth_texture = Texture::create_depth_texture(&self.device, &self.sc_desc, sample_count, "depth_texture");
+        self.multisampled_framebuffer = Self::create_multisampled_framebuffer(&self.device, &self.sc_desc, sample_count);
+        log::info!("MSAA: {}x", sample_count);
     }
 
-    fn input(&mut self, event: &WindowEvent) -> bool {
-        false
+    fn input(&mut self, event: &Event<()>) -> bool {
+        if self.gui.handle_event(event) {
+            return true;
+        }
+        let event = match event {
+            Event::WindowEvent { event, .. } => event,
+            _ => return false,
+        };
+
+        match event {
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::F12),
+                    ..
+                },
+                ..
+            } => {
+                let path = self.capture.screenshot();
+                log::info!("Saving screenshot to {}", path.display());
+                true
+            }
+            // F11 starts and stops recording every frame
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::F11),
+                    ..
+                },
+                ..
+            } => {
+                match self.capture.stop_recording() {
+                    Some(frames) => log::info!("Recorded {} frames", frames),
+                    None => log::info!("Recording to {}", self.capture.start_recording_new().display()),
+                }
+                true
+            }
+            // F1 shows and hides the GUI
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::F1),
+                    ..
+                },
+                ..
+            } => {
+                self.gui.visible = !self.gui.visible;
+                true
+            }
+            // M cycles through the MSAA sample counts
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::M),
+                    ..
+                },
+                ..
+            } => {
+                let next = config::SAMPLE_COUNTS.iter()
+                    .position(|count| *count == self.sample_count)
+                    .map_or(0, |i| (i + 1) % config::SAMPLE_COUNTS.len());
+                self.set_sample_count(config::SAMPLE_COUNTS[next]);
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::G),
+                    ..
+                },
+                ..
+            } => {
+                self.show_debug_gizmos = !self.show_debug_gizmos;
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::H),
+                    ..
+                },
+                ..
+            } => {
+                self.show_stats = !self.show_stats;
+                true
+            }
+            // R cycles through the render modes
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::R),
+                    ..
+                },
+                ..
+            } => {
+                self.render_mode = self.render_mode.next();
+                log::info!("Render mode: {}", self.render_mode.name());
+                true
+            }
+            WindowEvent::KeyboardInput { input, .. } => handle_keyboard_input(self, input),
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_position = *position;
+                true
+            }
+            // Left click selects the object under the cursor, or clears the selection
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } => {
+                let cursor = self.cursor_position;
+                match self.picking_mode {
+                    PickingMode::Ray => {
+                        self.selection_hit = self.pick_ray(cursor);
+                        self.selected_object = self.selection_hit.map(|hit| hit.object);
+                    }
+                    PickingMode::IdBuffer => {
+                        self.selection_hit = None;
+                        self.selected_object = self.id_buffer.pick(&self.device, &self.queue, &self.objects, &self.materials, &self.assets, cursor);
+                    }
+                }
+                match (self.selected_object, &self.selection_hit) {
+                    (Some(_), Some(hit)) => log::info!(
+                        "Picked {} at ({:.2}, {:.2}, {:.2}), {:.2} away",
+                        self.objects[hit.object].name, hit.position.x, hit.position.y, hit.position.z, hit.distance
+                    ),
+                    (Some(index), None) => log::info!("Picked {}", self.objects[index].name),
+                    (None, _) => log::info!("Picked nothing"),
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Nearest object under a position in the window, see picking::pick
+    fn pick_ray(&self, cursor: winit::dpi::PhysicalPosition<f64>) -> Option<Hit> {
+        let view_projection = self.render_camera.build_view_projection_matrix().ok()?;
+        let ray = Ray::from_cursor(cursor, self.size, view_projection)?;
+        picking::pick(&ray, &self.objects, &self.assets)
+    }
+
+    // Advances the simulation by one fixed timestep
+    fn update(&mut self, dt: Duration) {
+        self.previous_camera = self.camera.clone();
+
+        let mut direction = Vector3::zero();
+        for key in &self.held_keys {
+            match key {
+                VirtualKeyCode::W => direction.z -= 1.0,
+                VirtualKeyCode::S => direction.z += 1.0,
+                VirtualKeyCode::A => direction.x -= 1.0,
+                VirtualKeyCode::D => direction.x += 1.0,
+                VirtualKeyCode::E => direction.y += 1.0,
+                VirtualKeyCode::Q => direction.y -= 1.0,
+                _ => {}
+            }
+        }
+        if direction != Vector3::zero() {
+            let movement = direction.normalize() * CAMERA_SPEED * dt.as_secs_f32();
+            self.camera.camera_transform = self.camera.camera_transform.transform(Point3::from_vec(movement), Deg(0.0), Deg(0.0), Deg(0.0));
+        }
+    }
+
+    // Windows for inspecting and editing the scene and renderer while running
+    fn build_gui(&mut self) {
+        self.gui.begin_frame();
+        if !self.gui.visible {
+            return;
+        }
+        let ctx = self.gui.context();
+
+        egui::Window::new("Camera").default_pos([8.0, 120.0]).show(&ctx, |ui| {
+            if gui::edit_transform(ui, &mut self.camera.camera_transform) {
+                // Jump straight to the new transform instead of interpolating towards it
+                self.previous_camera = self.camera.clone();
+            }
+        });
+
+        egui::Window::new("Objects").default_pos([8.0, 260.0]).show(&ctx, |ui| {
+            for object in self.objects.iter_mut() {
+                let transform = &mut object.transform;
+                let mesh_status = self.assets.mesh_status(&object.mesh);
+                ui.collapsing(&object.name, |ui| {
+                    ui.label(format!("Mesh: {:?}", mesh_status));
+                    gui::edit_transform(ui, transform);
+                });
+            }
+        });
+
+        egui::Window::new("Light").default_pos([8.0, 420.0]).show(&ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Color");
+                ui.color_edit_button_rgb(&mut self.light.color);
+            });
+            ui.add(egui::Slider::new(&mut self.light.intensity, 0.0..=4.0).text("Intensity"));
+        });
+
+        egui::Window::new("Materials").default_pos([8.0, 480.0]).show(&ctx, |ui| {
+            for (i, material) in self.materials.iter_mut().enumerate() {
+                let texture_status = self.assets.texture_status(&material.texture);
+                ui.collapsing(format!("Material {}", i), |ui| {
+                    ui.label(format!("Texture: {:?}", texture_status));
+                    ui.add(egui::Slider::new(&mut material.metallic, 0.0..=1.0).text("Metallic"));
+                    ui.add(egui::Slider::new(&mut material.roughness, 0.0..=1.0).text("Roughness"));
+                    ui.add(egui::Slider::new(&mut material.normal_scale, 0.0..=2.0).text("Normal scale"));
+                });
+            }
+        });
+
+        egui::Window::new("Post Processing").default_pos([8.0, 640.0]).show(&ctx, |ui| {
+            let bloom = self.post_process.bloom_mut();
+            ui.checkbox(&mut bloom.enabled, "Bloom");
+            ui.add(egui::Slider::new(&mut bloom.threshold, 0.0..=8.0).text("Threshold"));
+            ui.add(egui::Slider::new(&mut bloom.soft_knee, 0.0..=1.0).text("Soft knee"));
+            ui.add(egui::Slider::new(&mut bloom.intensity, 0.0..=2.0).text("Intensity"));
+            ui.label(format!("{} mip levels", bloom.mip_level_count()));
+            for effect in self.post_process.effects_mut() {
+                ui.checkbox(&mut effect.enabled, &effect.name);
+                match effect.name.as_str() {
+                    post_process::TONEMAP => {
+                        let params: TonemapParams = effect.params();
+                        let mut operator = params.operator();
+                        let mut exposure = params.exposure;
+                        egui::ComboBox::from_label("Operator")
+                            .selected_text(operator.name())
+                            .show_ui(ui, |ui| {
+                                for option in TonemapOperator::ALL.iter() {
+                                    ui.selectable_value(&mut operator, *option, option.name());
+                                }
+                            });
+                        ui.add(egui::Slider::new(&mut exposure, 0.0..=8.0).logarithmic(true).text("Exposure"));
+                        effect.set_params(TonemapParams::new(operator, exposure));
+                        if ui.button("Auto exposure").clicked() {
+                            self.measure_exposure = true;
+                        }
+                    }
+                    post_process::COLOR_GRADING => {
+                        let mut params: post_process::ColorGradingParams = effect.params();
+                        ui.add(egui::Slider::new(&mut params.strength, 0.0..=1.0).text("Strength"));
+                        effect.set_params(params);
+                    }
+                    post_process::VIGNETTE => {
+                        let mut params: post_process::VignetteParams = effect.params();
+                        ui.add(egui::Slider::new(&mut params.intensity, 0.0..=1.0).text("Intensity"));
+                        ui.add(egui::Slider::new(&mut params.radius, 0.0..=1.0).text("Radius"));
+                        ui.add(egui::Slider::new(&mut params.softness, 0.01..=1.0).text("Softness"));
+                        effect.set_params(params);
+                    }
+                    post_process::GAMMA => {
+                        let mut params: post_process::GammaParams = effect.params();
+                        ui.add(egui::Slider::new(&mut params.gamma, 0.1..=4.0).text("Gamma"));
+                        effect.set_params(params);
+                    }
+                    _ => {}
+                }
+            }
+        });
+
+        // Applied after the window, changing these recreates GPU resources
+        let mut sample_count = self.sample_count;
+        let mut present_mode = self.sc_desc.present_mode;
+        let mut gizmo_depth_test = self.debug_draw.depth_test();
+        egui::Window::new("Renderer").default_pos([8.0, 520.0]).show(&ctx, |ui| {
+            egui::ComboBox::from_label("MSAA")
+                .selected_text(format!("{}x", sample_count))
+                .show_ui(ui, |ui| {
+                    for count in config::SAMPLE_COUNTS.iter() {
+                        ui.selectable_value(&mut sample_count, *count, format!("{}x", count));
+                    }
+                });
+            egui::ComboBox::from_label("Present mode")
+                .selected_text(format!("{:?}", present_mode))
+                .show_ui(ui, |ui| {
+                    for mode in [wgpu::PresentMode::Fifo, wgpu::PresentMode::Immediate, wgpu::PresentMode::Mailbox].iter() {
+                        ui.selectable_value(&mut present_mode, *mode, format!("{:?}", mode));
+                    }
+                });
+            ui.checkbox(&mut self.show_debug_gizmos, "Debug gizmos");
+            ui.checkbox(&mut gizmo_depth_test, "Depth test gizmos");
+            ui.checkbox(&mut self.frustum_culling, "Frustum culling");
+            ui.checkbox(&mut self.freeze_culling, "Freeze culling frustum");
+            ui.add(egui::Slider::new(&mut self.lod_hysteresis, 0.0..=0.5).text("LOD hysteresis"));
+            egui::ComboBox::from_label("Render mode")
+                .selected_text(self.render_mode.name())
+                .show_ui(ui, |ui| {
+                    for mode in RenderMode::ALL.iter() {
+                        ui.selectable_value(&mut self.render_mode, *mode, mode.name());
+                    }
+                });
+            if self.render_mode == RenderMode::Overdraw {
+                ui.add(egui::Slider::new(&mut self.render_modes.max_overdraw, 1.0..=32.0).text("Max overdraw"));
+            }
+            egui::ComboBox::from_label("Picking")
+                .selected_text(self.picking_mode.name())
+                .show_ui(ui, |ui| {
+                    for mode in PickingMode::ALL.iter() {
+                        ui.selectable_value(&mut self.picking_mode, *mode, mode.name());
+                    }
+                });
+            ui.checkbox(&mut self.show_stats, "Frame statistics");
+            if let Some(skybox) = &mut self.skybox {
+                ui.checkbox(&mut skybox.visible, "Skybox");
+            }
+            ui.horizontal(|ui| {
+                ui.label("Clear color");
+                ui.color_edit_button_rgb(&mut self.clear_color);
+            });
+        });
+
+        if sample_count != self.sample_count {
+            self.set_sample_count(sample_count);
+        }
+        if present_mode != self.sc_desc.present_mode {
+            self.sc_desc.present_mode = present_mode;
+            self.swap_chain = self.device.create_swap_chain(&self.surface, &self.sc_desc);
+        }
+        if gizmo_depth_test != self.debug_draw.depth_test() {
+            self.debug_draw.set_depth_test(&self.device, &self.assets, &mut self.pipeline_cache, gizmo_depth_test);
+        }
     }
 
-    fn render(&mut self) -> Result<(), wgpu::SwapChainError> {
+    // alpha is how far we are between the previous and the current fixed update
+    fn render(&mut self, alpha: f32) -> Result<(), wgpu::SwapChainError> {
+        // Built first so changes made in the GUI show up this frame
+        self.build_gui();
+
+        let loaded = self.assets.poll(&self.device, &self.queue);
+        for material in self.materials.iter_mut() {
+            if loaded.contains_texture(&material.texture) || loaded.contains_texture(&material.normal_map) {
+                material.bind_group = Material::create_bind_group(&self.device, &self.material_bind_group_layout, self.assets.texture(&material.texture), self.assets.texture(&material.normal_map));
+            }
+        }
+        self.assets.free_unused();
+
+        let camera = self.previous_camera.interpolate(&self.camera, alpha);
+        self.render_camera = camera.clone();
+        let view_projection = match camera.build_view_projection_matrix() {
+            Ok(view_projection) => view_projection,
+            Err(e) => {
+                log::error!("Skipping frame: {}", e);
+                return Ok(());
+            }
+        };
+        for object in &mut self.objects {
+            object.update_lod(&camera, &self.assets, self.lod_hysteresis);
+        }
+        for (index, object) in self.objects.iter().enumerate() {
+            let model = object.transform.compute_transformation_matrix();
+            let material = &self.materials[object.material];
+            let uniforms = ObjectUniforms {
+                mvp: (view_projection * model).into(),
+                model: model.into(),
+                camera_position: camera.position().extend(1.0).into(),
+                light: self.light.radiance(),
+                alpha_cutoff: material.alpha_mode.cutoff(),
+                metallic: material.metallic,
+                roughness: material.roughness,
+                normal_scale: material.normal_scale,
+                object_id: id_buffer::object_id(index),
+                _padding: [0; 3],
+            };
+            self.queue.write_buffer(&object.uniform_buffer, 0, bytemuck::cast_slice(&[uniforms]));
+        }
+        let mut draw_order = scene::draw_order(&self.objects, &self.materials, camera.position());
+        let drawable_count = draw_order.len();
+        if !self.freeze_culling {
+            self.culling_view_projection = view_projection;
+        }
+        if self.frustum_culling {
+            let frustum = Frustum::from_view_projection(self.culling_view_projection);
+            let (objects, assets) = (&self.objects, &self.assets);
+            draw_order.retain(|index| objects[*index].in_frustum(&frustum, assets.mesh(&objects[*index].mesh)));
+        }
+        let culled = drawable_count - draw_order.len();
+
+        if self.show_debug_gizmos {
+            self.debug_draw.grid(Point3::new(0.0, -0.5, 0.0), 0.25, 8, debug_draw::GREY);
+            for object in &self.objects {
+                let model = object.transform.compute_transformation_matrix();
+                self.debug_draw.axes(model, 0.25);
+                let bounds = self.assets.mesh(&object.mesh).bounds;
+                self.debug_draw.oriented_box(model, bounds.min, bounds.max, debug_draw::YELLOW);
+            }
+            if self.freeze_culling {
+                self.debug_draw.frustum(self.culling_view_projection, debug_draw::CYAN);
+            }
+            if let Some(index) = self.selected_object {
+                let model = self.objects[index].transform.compute_transformation_matrix();
+                let mesh = self.assets.mesh(&self.objects[index].mesh);
+                self.debug_draw.oriented_box(model, mesh.bounds.min, mesh.bounds.max, debug_draw::WHITE);
+                let (sphere, aabb) = self.objects[index].culling_bounds(mesh);
+                self.debug_draw.sphere(sphere.center, sphere.radius, debug_draw::CYAN);
+                self.debug_draw.aabb(aabb.min, aabb.max, debug_draw::CYAN);
+            }
+            if let Some(hit) = &self.selection_hit {
+                self.debug_draw.line(hit.position, hit.position + hit.normal * 0.25, debug_draw::WHITE);
+            }
+        }
+        let shaded = self.render_mode == RenderMode::Shaded;
+        self.render_modes.prepare(&self.device, &self.queue, self.render_mode, &camera, &self.objects, &self.assets, &loaded);
+        let skybox = self.skybox.as_ref().filter(|skybox| shaded && skybox.visible);
+        if let Some(skybox) = skybox {
+            if let std::result::Result::Ok(sky_view_projection) = camera.build_skybox_view_projection_matrix() {
+                skybox.prepare(&self.queue, sky_view_projection);
+            }
+        }
+        let draw_calls = draw_order.len()
+            + if self.debug_draw.line_count() > 0 { 1 } else { 0 }
+            + if skybox.is_some() { 1 } else { 0 };
+        self.debug_draw.prepare(&self.device, &self.queue, view_projection);
+
+        if self.show_stats {
+            let stats = self.clock.stats();
+            let position = camera.position();
+            let lod_levels = self.objects.iter()
+                .map(|object| object.lod.as_ref().map_or("-".to_string(), |lod| lod.level().to_string()))
+                .collect::<Vec<_>>()
+                .join(" ");
+            self.hud.text([8.0, 8.0], &format!(
+                "FPS: {:.0} ({:.2} ms)\nCamera: ({:.2}, {:.2}, {:.2})\nDraw calls: {}\nCulled: {}\nLOD levels: {}\nMSAA: {}x\nLoading: {} assets",
+                stats.fps,
+                stats.average_frame_time.as_secs_f32() * 1000.0,
+                position.x, position.y, position.z,
+                draw_calls,
+                culled,
+                lod_levels,
+                self.sample_count,
+                self.assets.pending(),
+            ));
+        }
+        self.hud.prepare(&self.device, &self.queue, self.size);
+
         let frame = self
             .swap_chain
             .get_current_frame()?
@@ -314,38 +1010,153 @@ impl State {
             label: Some("Render Encoder"),
         });
 
+        if let Some(profiler) = &mut self.profiler {
+            profiler.begin_scope("Render Pass", &mut encoder);
+        }
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[
+                    // With MSAA we render into the multisampled framebuffer and resolve into the frame
                     wgpu::RenderPassColorAttachment {
-                        view: &frame.view,
-                        resolve_target: None,
+                        view: self.multisampled_framebuffer.as_ref().unwrap_or_else(|| self.post_process.scene_view()),
+                        resolve_target: self.multisampled_framebuffer.as_ref().map(|_| self.post_process.scene_view()),
                         ops: wgpu::Operations {
-                            load: wgpu::LoadOp::Clear(
+                            // Debug views start out black, overdraw counts from 0
+                            load: wgpu::LoadOp::Clear(if shaded {
                                 wgpu::Color {
-                                    r: 0.1,
-                                    g: 0.2,
-                                    b: 0.3,
+                                    r: self.clear_color[0] as f64,
+                                    g: self.clear_color[1] as f64,
+                                    b: self.clear_color[2] as f64,
                                     a: 1.0,
                                 }
-                            ),
+                            } else {
+                                wgpu::Color::BLACK
+                            }),
+                            store: true,
+                        }
+                    }
+                ],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth_texture.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            if let Some(skybox) = skybox {
+                skybox.draw(&mut render_pass);
+            }
+            if shaded {
+                render_pass.set_bind_group(2, self.environment.bind_group(), &[]);
+                for index in draw_order {
+                    let object = &self.objects[index];
+                    let material = &self.materials[object.material];
+                    render_pass.set_pipeline(self.material_pipelines.get(material.alpha_mode)); // 2.
+                    render_pass.set_bind_group(0, &material.bind_group, &[]); // NEW!
+                    render_pass.set_bind_group(1, &object.uniform_bind_group, &[]);
+                    let mesh = self.assets.mesh(&object.mesh);
+                    render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                    render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32); // 1.
+                    render_pass.draw_indexed(0..mesh.num_indices, 0, 0..1); // 3.
+                }
+            } else {
+                self.render_modes.draw(&mut render_pass, self.render_mode, &draw_order, &self.objects, &self.materials, &self.assets);
+            }
+            // Lines would count as overdraw
+            if self.render_mode != RenderMode::Overdraw {
+                self.debug_draw.draw(&mut render_pass);
+            }
+        }
+
+        // Post processing overwrites the HDR scene, so it's measured before the rest of the frame
+        // has been recorded
+        if self.measure_exposure && shaded {
+            self.measure_exposure = false;
+            self.queue.submit(std::iter::once(encoder.finish()));
+            encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Render Encoder"),
+            });
+            let measured = self.luminance.measure(&self.device, &self.queue, self.post_process.scene_view(), self.sc_desc.width, self.sc_desc.height);
+            match measured {
+                Ok(exposure) => {
+                    if let Some(tonemap) = self.post_process.effect_mut(post_process::TONEMAP) {
+                        let params: TonemapParams = tonemap.params();
+                        tonemap.set_params(TonemapParams::new(params.operator(), exposure));
+                    }
+                    log::info!("Exposure set to {:.2}", exposure);
+                }
+                Err(e) => log::error!("{:?}", e),
+            }
+        }
+
+        if let Some(profiler) = &mut self.profiler {
+            profiler.end_scope(&mut encoder);
+            profiler.begin_scope("Post Processing", &mut encoder);
+        }
+        match self.render_mode {
+            RenderMode::Shaded => self.post_process.run(&self.queue, &mut encoder, &self.frame_texture.view),
+            RenderMode::Overdraw => {
+                let params = HeatMapParams { max_count: self.render_modes.max_overdraw };
+                self.post_process.run_heat_map(&self.queue, &mut encoder, &self.frame_texture.view, params);
+            }
+            _ => self.post_process.run_copy(&self.queue, &mut encoder, &self.frame_texture.view),
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.end_scope(&mut encoder);
+            profiler.begin_scope("HUD Pass", &mut encoder);
+        }
+        self.hud.render(&mut encoder, &self.frame_texture.view);
+        if let Some(profiler) = &mut self.profiler {
+            profiler.end_scope(&mut encoder);
+            profiler.begin_scope("GUI Pass", &mut encoder);
+        }
+        self.gui.render(&self.device, &self.queue, &mut encoder, &self.frame_texture.view);
+        if let Some(profiler) = &mut self.profiler {
+            profiler.end_scope(&mut encoder);
+        }
+
+        if self.capture.wants_frame() {
+            self.capture.copy_frame(&self.device, &mut encoder, &self.frame_texture, self.sc_desc.width, self.sc_desc.height, self.sc_desc.format);
+        }
+
+        if let Some(profiler) = &mut self.profiler {
+            profiler.begin_scope("Present Pass", &mut encoder);
+        }
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Present Pass"),
+                color_attachments: &[
+                    wgpu::RenderPassColorAttachment {
+                        view: &frame.view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
                             store: true,
                         }
                     }
                 ],
                 depth_stencil_attachment: None,
             });
-            render_pass.set_pipeline(&self.render_pipeline); // 2.
-            render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]); // NEW!
-            render_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16); // 1.
-            render_pass.draw_indexed(0..self.num_indices, 0, 0..1); // 3.
+            render_pass.set_pipeline(&self.blit_pipeline);
+            render_pass.set_bind_group(0, &self.frame_bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.end_scope(&mut encoder);
+            profiler.resolve(&mut encoder);
         }
 
         // submit will accept anything that implements IntoIter
         self.queue.submit(std::iter::once(encoder.finish()));
+        self.capture.finish(&self.device);
+
+        if let Some(profiler) = &mut self.profiler {
+            profiler.end_frame(&self.device);
+            log::debug!("Frame timings:\n{}", profiler.report());
+        }
 
         Ok(())
     }
@@ -353,59 +1164,79 @@ impl State {
 
 }
 
-fn handle_keyboard_input(state: &mut State, input: KeyboardInput) {
-    let mut camera_movement = Point3 {
-        x: 0.0,
-        y: 0.0,
-        z: 0.0,
+// Tracks which keys are held, returns whether the key is used by the engine
+fn handle_keyboard_input(state: &mut State, input: &KeyboardInput) -> bool {
+    let key = match input.virtual_keycode {
+        Some(key @ (VirtualKeyCode::W | VirtualKeyCode::A | VirtualKeyCode::S | VirtualKeyCode::D | VirtualKeyCode::Q | VirtualKeyCode::E)) => key,
+        _ => return false,
     };
 
-    
-    match input {
-        KeyboardInput {
-            state: ElementState::Pressed,
-            virtual_keycode: Some(VirtualKeyCode::W),
-            ..
-        } => {
-            camera_movement.x += 1.0
-        }
-        ,
-        _ => {}
-    }
-
-    // let new_camera_transform = state.camera.camera_transform.transform(camera_movement,  Deg(0.0), Deg(0.0), Deg(0.0));
+    match input.state {
+        ElementState::Pressed => state.held_keys.insert(key),
+        ElementState::Released => state.held_keys.remove(&key),
+    };
+    true
 }
 
 
 fn main() {
 
-    env_logger::init();
+    // The engine's own messages are shown without RUST_LOG, wgpu's only when they're warnings
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn,wgpu_rs_custom_engine=info")).init();
+
+    let config = match RendererConfig::from_args(std::env::args()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{:?}", e);
+            std::process::exit(2);
+        }
+    };
+    if config.list_adapters {
+        let adapters = config::describe_adapters();
+        if adapters.is_empty() {
+            log::info!("No adapters found");
+        }
+        for adapter in adapters {
+            log::info!("{}", adapter);
+        }
+        return;
+    }
+
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new().build(&event_loop).unwrap();
 
-    let mut state = block_on(State::new(&window));
+    let trace_file = config.trace_file.clone();
+    let mut state = match block_on(State::new(&window, &config)) {
+        Ok(state) => state,
+        Err(e) => {
+            log::error!("Failed to initialize the renderer: {}", e);
+            let mut source = std::error::Error::source(&e);
+            while let Some(cause) = source {
+                log::error!("    caused by: {}", cause);
+                source = cause.source();
+            }
+            std::process::exit(1);
+        }
+    };
 
     event_loop.run(move |event, _, control_flow| {
         match event {
+            // Events the engine or the GUI handled
+            Event::WindowEvent { window_id, .. } if window_id == window.id() && state.input(&event) => {}
             Event::WindowEvent {
                 ref event,
                 window_id,
-            } if window_id == window.id() => if !state.input(event) { // UPDATED!
+            } if window_id == window.id() => { // UPDATED!
                 match event {
                     WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
                     WindowEvent::KeyboardInput {
-                        input,
+                        input: KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::Escape),
+                            ..
+                        },
                         ..
-                    } => {
-                        match input {
-                            KeyboardInput {
-                                state: ElementState::Pressed,
-                                virtual_keycode: Some(VirtualKeyCode::Escape),
-                                ..
-                            } => *control_flow = ControlFlow::Exit,
-                            _ => {}
-                        }
-                    }
+                    } => *control_flow = ControlFlow::Exit,
                     WindowEvent::Resized(physical_size) => {
                         state.resize(*physical_size);
                     }
@@ -416,15 +1247,27 @@ fn main() {
                 }
             },
             Event::RedrawRequested(_) => {
-                // state.update();
-                match state.render() {
+                let tick = state.clock.tick();
+                for _ in 0..tick.fixed_steps {
+                    state.update(tick.fixed_timestep);
+                }
+
+                let stats = *state.clock.stats();
+                if stats.total_frames % 60 == 0 {
+                    window.set_title(&format!("wgpu_rs_custom_engine - {:.0} fps ({:.2} ms)", stats.fps, stats.average_frame_time.as_secs_f32() * 1000.0));
+                    if let Some(profiler) = &state.profiler {
+                        log::info!("Frame timings:\n{}", profiler.report());
+                    }
+                }
+
+                match state.render(tick.alpha) {
                     Ok(_) => {}
                     // Recreate the swap_chain if lost
                     Err(wgpu::SwapChainError::Lost) => state.resize(state.size),
                     // The system is out of memory, we should probably quit
                     Err(wgpu::SwapChainError::OutOfMemory) => *control_flow = ControlFlow::Exit,
                     // All other errors (Outdated, Timeout) should be resolved by the next frame
-                    Err(e) => eprintln!("{:?}", e),
+                    Err(e) => log::error!("{:?}", e),
                 }
             }
             Event::MainEventsCleared => {
@@ -432,6 +1275,16 @@ fn main() {
                 // request it.
                 window.request_redraw();
             },
+            Event::LoopDestroyed => {
+                // The event loop exits the process without dropping the state
+                state.capture.shutdown();
+                if let (Some(profiler), Some(path)) = (&state.profiler, &trace_file) {
+                    match profiler.write_chrome_trace(path) {
+                        Ok(()) => log::info!("Wrote trace to {}", path.display()),
+                        Err(e) => log::error!("{:?}", e),
+                    }
+                }
+            }
             _ => {}
         }
     });
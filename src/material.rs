@@ -0,0 +1,176 @@
+use std::sync::Arc;
+
+use crate::asset_manager::{AssetManager, Handle};
+use crate::pipeline::{DepthSettings, PipelineBuilder, PipelineCache};
+use crate::texture::Texture;
+use crate::vertex::Vertex;
+
+// Faces every material pipeline skips, which picking::pick skips as well
+pub const CULL_MODE: Option<wgpu::Face> = Some(wgpu::Face::Back);
+
+// Blended fragments fainter than this can't be picked through the id buffer
+const BLEND_PICKING_CUTOFF: f32 = 0.1;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AlphaMode {
+    // Alpha is ignored
+    Opaque,
+    // Fragments with alpha below the cutoff are discarded, everything else is opaque
+    Mask { cutoff: f32 },
+    // Alpha blended, drawn back to front after everything opaque
+    Blend,
+}
+
+impl AlphaMode {
+    pub fn is_transparent(&self) -> bool {
+        matches!(self, AlphaMode::Blend)
+    }
+
+    // Fragments with less alpha are discarded by the mask shader, and by the id and render mode
+    // passes for masked and blended materials so only what's visible is picked and inspected.
+    // The blend shader ignores it.
+    pub fn cutoff(&self) -> f32 {
+        match self {
+            AlphaMode::Opaque => 0.0,
+            AlphaMode::Mask { cutoff } => *cutoff,
+            AlphaMode::Blend => BLEND_PICKING_CUTOFF,
+        }
+    }
+}
+
+pub struct Material {
+    pub texture: Handle<Texture>,
+    // Tangent space, OpenGL convention with green pointing up the texture. Texture::flat_normal_map
+    // leaves the surface as it is.
+    pub normal_map: Handle<Texture>,
+    // Scales the bumps of the normal map, 0 ignores it
+    pub normal_scale: f32,
+    pub alpha_mode: AlphaMode,
+    // 0 is a dielectric like plastic or wood, 1 a bare metal
+    pub metallic: f32,
+    // 0 is a perfect mirror, 1 fully diffuse
+    pub roughness: f32,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl Material {
+    pub fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let texture = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        let sampler = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::Sampler {
+                comparison: false,
+                filtering: true,
+            },
+            count: None,
+        };
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[texture(0), sampler(1), texture(2), sampler(3)],
+            label: Some("material_bind_group_layout"),
+        })
+    }
+
+    // Has to be recreated whenever one of the textures is replaced, e.g. once it finished loading
+    pub fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        diffuse: &Texture,
+        normal_map: &Texture
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&diffuse.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&diffuse.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&normal_map.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&normal_map.sampler),
+                },
+            ],
+            label: Some("material_bind_group"),
+        })
+    }
+}
+
+// One pipeline per alpha mode, all sharing the same shader and layout
+pub struct MaterialPipelines {
+    pub opaque: Arc<wgpu::RenderPipeline>,
+    pub mask: Arc<wgpu::RenderPipeline>,
+    pub blend: Arc<wgpu::RenderPipeline>,
+}
+
+impl MaterialPipelines {
+    pub fn build(
+        device: &wgpu::Device,
+        assets: &AssetManager,
+        cache: &mut PipelineCache,
+        layout: &Arc<wgpu::PipelineLayout>,
+        shader: &Handle<wgpu::ShaderModule>,
+        color_format: wgpu::TextureFormat,
+        sample_count: u32
+    ) -> Self {
+        let builder = |label: &str, depth_write: bool| {
+            PipelineBuilder::new(label, layout, shader, color_format)
+                .vertex_layout(Vertex::desc())
+                .cull_mode(CULL_MODE)
+                .depth(Some(DepthSettings {
+                    format: Texture::DEPTH_FORMAT,
+                    write_enabled: depth_write,
+                    compare: wgpu::CompareFunction::Less,
+                }))
+                .sample_count(sample_count)
+        };
+
+        let opaque = builder("Opaque Pipeline", true)
+            .build(device, assets, cache);
+        let mask = builder("Mask Pipeline", true)
+            .fragment_entry_point(Some("main_mask"))
+            .build(device, assets, cache);
+        // Transparent objects are depth tested against opaque ones, but don't occlude each other
+        let blend = builder("Blend Pipeline", false)
+            .blend(Some(wgpu::BlendState::ALPHA_BLENDING))
+            .build(device, assets, cache);
+
+        Self { opaque, mask, blend }
+    }
+
+    pub fn get(&self, alpha_mode: AlphaMode) -> &wgpu::RenderPipeline {
+        match alpha_mode {
+            AlphaMode::Opaque => &self.opaque,
+            AlphaMode::Mask { .. } => &self.mask,
+            AlphaMode::Blend => &self.blend,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn discards_faint_fragments_of_masked_and_blended_materials() {
+        assert_eq!(AlphaMode::Opaque.cutoff(), 0.0);
+        assert_eq!(AlphaMode::Mask { cutoff: 0.5 }.cutoff(), 0.5);
+        assert!(AlphaMode::Blend.cutoff() > 0.0);
+    }
+}
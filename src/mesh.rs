@@ -0,0 +1,257 @@
+use std::path::Path;
+
+use anyhow::*;
+use cgmath::{InnerSpace, Point3, Vector3, Zero};
+use wgpu::util::DeviceExt;
+
+use crate::culling::Aabb;
+use crate::vertex::Vertex;
+
+pub struct Mesh {
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    pub num_indices: u32,
+    // Object space, around every vertex
+    pub bounds: Aabb,
+    // CPU copy of the triangles for picking
+    pub positions: Vec<Point3<f32>>,
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    pub fn new(
+        device: &wgpu::Device,
+        vertices: &[Vertex],
+        indices: &[u32],
+        label: &str
+    ) -> Self {
+        let vertex_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{} Vertex Buffer", label)),
+                contents: bytemuck::cast_slice(vertices),
+                usage: wgpu::BufferUsage::VERTEX,
+            }
+        );
+
+        let index_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{} Index Buffer", label)),
+                contents: bytemuck::cast_slice(indices),
+                usage: wgpu::BufferUsage::INDEX,
+            }
+        );
+
+        Self {
+            vertex_buffer,
+            index_buffer,
+            num_indices: indices.len() as u32,
+            bounds: Aabb::from_points(vertices.iter().map(|vertex| vertex.position.into())),
+            positions: vertices.iter().map(|vertex| vertex.position.into()).collect(),
+            indices: indices.to_vec(),
+        }
+    }
+
+    pub fn from_data(device: &wgpu::Device, data: &MeshData, label: &str) -> Self {
+        Self::new(device, &data.vertices, &data.indices, label)
+    }
+}
+
+// CPU side mesh data, which can be built off the render thread and uploaded later
+#[derive(Debug, Clone)]
+pub struct MeshData {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    // Loads every model in an .obj file and merges them into a single mesh
+    pub fn from_obj(path: &Path) -> Result<Self> {
+        let (models, _materials) = tobj::load_obj(path, &tobj::LoadOptions {
+            triangulate: true,
+            single_index: true,
+            ..Default::default()
+        })?;
+
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        for model in models {
+            let mesh = model.mesh;
+            let offset = vertices.len() as u32;
+            for i in 0..mesh.positions.len() / 3 {
+                let tex_coords = if mesh.texcoords.is_empty() {
+                    [0.0, 0.0]
+                } else {
+                    // OBJ has v pointing up, wgpu has it pointing down
+                    [mesh.texcoords[i * 2], 1.0 - mesh.texcoords[i * 2 + 1]]
+                };
+                let normal = if mesh.normals.is_empty() {
+                    [0.0, 0.0, 0.0]
+                } else {
+                    [mesh.normals[i * 3], mesh.normals[i * 3 + 1], mesh.normals[i * 3 + 2]]
+                };
+                vertices.push(Vertex {
+                    position: [mesh.positions[i * 3], mesh.positions[i * 3 + 1], mesh.positions[i * 3 + 2]],
+                    tex_coords,
+                    normal,
+                    tangent: [0.0; 4],
+                });
+            }
+            indices.extend(mesh.indices.iter().map(|index| index + offset));
+            if mesh.normals.is_empty() {
+                compute_normals(&mut vertices[offset as usize..], &mesh.indices);
+            }
+            // OBJ files can't store tangents
+            compute_tangents(&mut vertices[offset as usize..], &mesh.indices);
+        }
+
+        if indices.is_empty() {
+            bail!("{} contains no triangles", path.display());
+        }
+
+        Ok(Self { vertices, indices })
+    }
+
+    // Unit cube centered on the origin, with each face mapping the whole texture
+    pub fn cube() -> Self {
+        let faces: [([f32; 3], [f32; 3], [f32; 3]); 6] = [
+            // normal, u axis, v axis
+            ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+            ([0.0, 0.0, -1.0], [-1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+            ([1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, -1.0, 0.0]),
+            ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, -1.0, 0.0]),
+            ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+            ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+        ];
+
+        let mut vertices = Vec::with_capacity(24);
+        let mut indices = Vec::with_capacity(36);
+        for (normal, u, v) in faces.iter() {
+            let offset = vertices.len() as u32;
+            for (s, t) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)].iter() {
+                let position = [
+                    0.5 * normal[0] + (s - 0.5) * u[0] + (t - 0.5) * v[0],
+                    0.5 * normal[1] + (s - 0.5) * u[1] + (t - 0.5) * v[1],
+                    0.5 * normal[2] + (s - 0.5) * u[2] + (t - 0.5) * v[2],
+                ];
+                vertices.push(Vertex { position, tex_coords: [*s, *t], normal: *normal, tangent: [0.0; 4] });
+            }
+            indices.extend_from_slice(&[offset, offset + 2, offset + 1, offset, offset + 3, offset + 2]);
+        }
+        compute_tangents(&mut vertices, &indices);
+
+        Self { vertices, indices }
+    }
+}
+
+// Smooth normals for meshes that don't come with any, averaging the faces around every vertex
+// weighted by their area
+pub fn compute_normals(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut normals = vec![Vector3::zero(); vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize];
+        let position = |i: usize| Vector3::from(vertices[i].position);
+        // Not normalized, so larger faces count more
+        let face_normal = (position(b) - position(a)).cross(position(c) - position(a));
+        for &i in &[a, b, c] {
+            normals[i] += face_normal;
+        }
+    }
+    for (vertex, normal) in vertices.iter_mut().zip(normals) {
+        if normal.magnitude2() > 0.0 {
+            vertex.normal = normal.normalize().into();
+        }
+    }
+}
+
+// Per vertex tangent frames for normal mapping, in the spirit of MikkTSpace: the tangents and
+// bitangents of every face follow its texture coordinates, are summed per vertex weighted by
+// the face's area in uv space, and orthogonalized against the vertex normal
+pub fn compute_tangents(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut tangents = vec![Vector3::zero(); vertices.len()];
+    let mut bitangents = vec![Vector3::zero(); vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize];
+        let position = |i: usize| Vector3::from(vertices[i].position);
+        let uv = |i: usize| vertices[i].tex_coords;
+        let (edge1, edge2) = (position(b) - position(a), position(c) - position(a));
+        let (du1, dv1) = (uv(b)[0] - uv(a)[0], uv(b)[1] - uv(a)[1]);
+        let (du2, dv2) = (uv(c)[0] - uv(a)[0], uv(c)[1] - uv(a)[1]);
+        let determinant = du1 * dv2 - du2 * dv1;
+        if determinant.abs() < f32::EPSILON {
+            // Degenerate texture coordinates don't define a direction
+            continue;
+        }
+        // Left unnormalized, so faces taking up more of the texture count more
+        let tangent = (edge1 * dv2 - edge2 * dv1) * determinant.signum();
+        let bitangent = (edge2 * du1 - edge1 * du2) * determinant.signum();
+        for &i in &[a, b, c] {
+            tangents[i] += tangent;
+            bitangents[i] += bitangent;
+        }
+    }
+
+    for ((vertex, tangent), bitangent) in vertices.iter_mut().zip(tangents).zip(bitangents) {
+        let normal = Vector3::from(vertex.normal);
+        let mut tangent = tangent - normal * normal.dot(tangent);
+        if tangent.magnitude2() < f32::EPSILON {
+            // Any direction perpendicular to the normal works when nothing better is known
+            let axis = if normal.x.abs() < 0.9 { Vector3::unit_x() } else { Vector3::unit_y() };
+            tangent = axis - normal * normal.dot(axis);
+        }
+        let tangent = tangent.normalize();
+        let handedness = if normal.cross(tangent).dot(bitangent) < 0.0 { -1.0 } else { 1.0 };
+        vertex.tangent = [tangent.x, tangent.y, tangent.z, handedness];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computed_normals_face_the_winding_direction() {
+        // Counter clockwise seen from +z
+        let mut vertices = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]
+            .iter()
+            .map(|position| Vertex { position: *position, tex_coords: [0.0, 0.0], normal: [0.0, 0.0, 0.0], tangent: [0.0; 4] })
+            .collect::<Vec<_>>();
+        compute_normals(&mut vertices, &[0, 1, 2]);
+        for vertex in &vertices {
+            assert_eq!(vertex.normal, [0.0, 0.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn cube_normals_point_out_of_their_faces() {
+        let cube = MeshData::cube();
+        for vertex in &cube.vertices {
+            let position = Vector3::from(vertex.position);
+            assert!(position.dot(Vector3::from(vertex.normal)) > 0.0);
+        }
+    }
+
+    #[test]
+    fn tangents_follow_the_texture_coordinates() {
+        // A quad facing +z with u along +x and v pointing down, like the pentagon
+        let corners = [([0.0, 0.0, 0.0], [0.0, 1.0]), ([1.0, 0.0, 0.0], [1.0, 1.0]), ([1.0, 1.0, 0.0], [1.0, 0.0]), ([0.0, 1.0, 0.0], [0.0, 0.0])];
+        let mut vertices = corners.iter()
+            .map(|(position, tex_coords)| Vertex { position: *position, tex_coords: *tex_coords, normal: [0.0, 0.0, 1.0], tangent: [0.0; 4] })
+            .collect::<Vec<_>>();
+        compute_tangents(&mut vertices, &[0, 1, 2, 0, 2, 3]);
+        for vertex in &vertices {
+            assert_eq!(vertex.tangent, [1.0, 0.0, 0.0, -1.0]);
+        }
+    }
+
+    #[test]
+    fn cube_tangents_are_perpendicular_to_the_normals() {
+        let cube = MeshData::cube();
+        for vertex in &cube.vertices {
+            let [x, y, z, w] = vertex.tangent;
+            let tangent = Vector3::new(x, y, z);
+            assert!(tangent.dot(Vector3::from(vertex.normal)).abs() < 1e-6);
+            assert!((tangent.magnitude() - 1.0).abs() < 1e-6);
+            assert!(w == 1.0 || w == -1.0);
+        }
+    }
+}
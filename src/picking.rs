@@ -0,0 +1,291 @@
+use cgmath::{InnerSpace, Matrix, Matrix4, Point3, SquareMatrix, Transform, Vector3};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+use crate::asset_manager::AssetManager;
+use crate::culling::Aabb;
+use crate::material;
+use crate::scene::SceneObject;
+
+// Triangles closer to parallel with the ray than this are treated as missed
+const PARALLEL_EPSILON: f32 = 1e-7;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PickingMode {
+    // CPU ray cast against the meshes, see pick
+    Ray,
+    // GPU id buffer readback, see IdBuffer::pick
+    IdBuffer,
+}
+
+impl PickingMode {
+    pub const ALL: [PickingMode; 2] = [PickingMode::Ray, PickingMode::IdBuffer];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PickingMode::Ray => "Ray cast",
+            PickingMode::IdBuffer => "Id buffer",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    // Normalized for rays in world space, so distances along it are world space distances
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    // Ray from the near plane through a cursor position in window pixels, with y pointing down.
+    // Takes Camera::build_view_projection_matrix, whose clip space depth goes from 0 at the near
+    // plane to 1 at the far plane because of OPENGL_TO_WGPU_MATRIX.
+    pub fn from_cursor(cursor: PhysicalPosition<f64>, size: PhysicalSize<u32>, view_projection: Matrix4<f32>) -> Option<Self> {
+        if size.width == 0 || size.height == 0 {
+            return None;
+        }
+        let inverse = view_projection.invert()?;
+        let x = (2.0 * cursor.x / size.width as f64 - 1.0) as f32;
+        let y = (1.0 - 2.0 * cursor.y / size.height as f64) as f32;
+        let near = inverse.transform_point(Point3::new(x, y, 0.0));
+        let far = inverse.transform_point(Point3::new(x, y, 1.0));
+        Some(Self { origin: near, direction: (far - near).normalize() })
+    }
+
+    pub fn at(&self, distance: f32) -> Point3<f32> {
+        self.origin + self.direction * distance
+    }
+
+    // The direction isn't renormalized, so distances along the transformed ray match the original
+    pub fn transform(&self, transform: Matrix4<f32>) -> Self {
+        Self {
+            origin: transform.transform_point(self.origin),
+            direction: transform.transform_vector(self.direction),
+        }
+    }
+
+    // Distance to where the ray enters the box, 0 if it starts inside
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32> {
+        let (mut enter, mut exit) = (0.0f32, f32::INFINITY);
+        for axis in 0..3 {
+            let inverse = 1.0 / self.direction[axis];
+            let near = (aabb.min[axis] - self.origin[axis]) * inverse;
+            let far = (aabb.max[axis] - self.origin[axis]) * inverse;
+            // NaN when the ray runs along a face of the box, min and max skip those
+            enter = enter.max(near.min(far));
+            exit = exit.min(near.max(far));
+        }
+        if enter <= exit {
+            Some(enter)
+        } else {
+            None
+        }
+    }
+
+    // Möller–Trumbore. Returns the distance and the weights of the three corners at the hit.
+    // Triangles are front facing when their corners go counter clockwise as seen by the ray,
+    // like wgpu::FrontFace::Ccw, and the side cull_mode names is missed.
+    pub fn intersect_triangle(&self, triangle: [Point3<f32>; 3], cull_mode: Option<wgpu::Face>) -> Option<(f32, [f32; 3])> {
+        let [a, b, c] = triangle;
+        let (edge1, edge2) = (b - a, c - a);
+        let p = self.direction.cross(edge2);
+        // Positive when the ray sees the front
+        let determinant = edge1.dot(p);
+        if determinant.abs() < PARALLEL_EPSILON {
+            return None;
+        }
+        match cull_mode {
+            Some(wgpu::Face::Back) if determinant < 0.0 => return None,
+            Some(wgpu::Face::Front) if determinant > 0.0 => return None,
+            _ => {}
+        }
+        let offset = self.origin - a;
+        let u = offset.dot(p) / determinant;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = offset.cross(edge1);
+        let v = self.direction.dot(q) / determinant;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let distance = edge2.dot(q) / determinant;
+        if distance < 0.0 {
+            return None;
+        }
+        Some((distance, [1.0 - u - v, u, v]))
+    }
+}
+
+// Closest triangle the ray hits within max_distance: the distance, the triangle's index and the
+// weights of its corners
+pub fn intersect_triangles(
+    ray: &Ray,
+    positions: &[Point3<f32>],
+    indices: &[u32],
+    cull_mode: Option<wgpu::Face>,
+    max_distance: f32
+) -> Option<(f32, usize, [f32; 3])> {
+    let mut closest = None;
+    let mut max_distance = max_distance;
+    for (i, triangle) in indices.chunks_exact(3).enumerate() {
+        let corners = [positions[triangle[0] as usize], positions[triangle[1] as usize], positions[triangle[2] as usize]];
+        if let Some((distance, barycentrics)) = ray.intersect_triangle(corners, cull_mode) {
+            if distance < max_distance {
+                max_distance = distance;
+                closest = Some((distance, i, barycentrics));
+            }
+        }
+    }
+    closest
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Hit {
+    // Index into the scene's objects
+    pub object: usize,
+    // World space distance from the ray's origin
+    pub distance: f32,
+    pub position: Point3<f32>,
+    // World space normal of the triangle, facing back along the ray
+    pub normal: Vector3<f32>,
+    // Index of the triangle in the object's mesh
+    pub triangle: usize,
+    // Weights of the triangle's three corners at the hit
+    pub barycentrics: [f32; 3],
+}
+
+// Nearest object the ray hits, testing the triangles of the mesh each object currently draws
+// and skipping the faces rendering culls, see material::CULL_MODE.
+// Objects whose bounding box is further away than the nearest hit so far are skipped.
+pub fn pick(ray: &Ray, objects: &[SceneObject], assets: &AssetManager) -> Option<Hit> {
+    let mut nearest: Option<Hit> = None;
+    for (index, object) in objects.iter().enumerate() {
+        let model = object.transform.compute_transformation_matrix();
+        let inverse = match model.invert() {
+            Some(inverse) => inverse,
+            None => continue,
+        };
+        let mesh = assets.mesh(&object.mesh);
+        // Tested in object space, the mesh's data is there
+        let local_ray = ray.transform(inverse);
+        let max_distance = nearest.map_or(f32::INFINITY, |hit| hit.distance);
+        match local_ray.intersect_aabb(&mesh.bounds) {
+            Some(distance) if distance < max_distance => {}
+            _ => continue,
+        }
+        // Mirroring transforms flip the winding the GPU sees
+        let cull_mode = match material::CULL_MODE {
+            Some(wgpu::Face::Back) if model.determinant() < 0.0 => Some(wgpu::Face::Front),
+            Some(wgpu::Face::Front) if model.determinant() < 0.0 => Some(wgpu::Face::Back),
+            cull_mode => cull_mode,
+        };
+        if let Some((distance, triangle, barycentrics)) = intersect_triangles(&local_ray, &mesh.positions, &mesh.indices, cull_mode, max_distance) {
+            let corner = |i: usize| mesh.positions[mesh.indices[triangle * 3 + i] as usize];
+            let local_normal = (corner(1) - corner(0)).cross(corner(2) - corner(0));
+            // Normals transform with the inverse transpose to stay perpendicular under non-uniform scale
+            let normal = (inverse.transpose() * local_normal.extend(0.0)).truncate().normalize();
+            let normal = if normal.dot(ray.direction) > 0.0 { -normal } else { normal };
+            nearest = Some(Hit {
+                object: index,
+                distance,
+                position: ray.at(distance),
+                normal,
+                triangle,
+                barycentrics,
+            });
+        }
+    }
+    nearest
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Deg;
+
+    use super::*;
+    use crate::camera::OPENGL_TO_WGPU_MATRIX;
+
+    const EPSILON: f32 = 1e-4;
+
+    fn view_projection() -> Matrix4<f32> {
+        // Camera at (0, 0, 2) looking down -z
+        let view = Matrix4::from_translation(Vector3::new(0.0, 0.0, 2.0)).invert().unwrap();
+        OPENGL_TO_WGPU_MATRIX * cgmath::perspective(Deg(90.0), 2.0, 0.1, 10.0) * view
+    }
+
+    #[test]
+    fn cursor_rays_start_on_the_near_plane() {
+        let size = PhysicalSize::new(800, 400);
+        let center = Ray::from_cursor(PhysicalPosition::new(400.0, 200.0), size, view_projection()).unwrap();
+        assert!((center.origin - Point3::new(0.0, 0.0, 1.9)).magnitude() < EPSILON);
+        assert!((center.direction - Vector3::new(0.0, 0.0, -1.0)).magnitude() < EPSILON);
+
+        // The top edge of a 90 degree field of view is 45 degrees up, and y points down in the window
+        let top = Ray::from_cursor(PhysicalPosition::new(400.0, 0.0), size, view_projection()).unwrap();
+        let diagonal = std::f32::consts::FRAC_1_SQRT_2;
+        assert!((top.direction - Vector3::new(0.0, diagonal, -diagonal)).magnitude() < EPSILON);
+
+        assert_eq!(Ray::from_cursor(PhysicalPosition::new(0.0, 0.0), PhysicalSize::new(0, 0), view_projection()), None);
+    }
+
+    #[test]
+    fn hits_the_sides_that_are_not_culled() {
+        let triangle = [Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 0.0, 0.0), Point3::new(0.0, 1.0, 0.0)];
+        let front = Ray { origin: Point3::new(0.25, 0.5, 3.0), direction: -Vector3::unit_z() };
+        let (distance, barycentrics) = front.intersect_triangle(triangle, Some(wgpu::Face::Back)).unwrap();
+        assert!((distance - 3.0).abs() < EPSILON);
+        assert!((barycentrics[0] - 0.25).abs() < EPSILON);
+        assert!((barycentrics[1] - 0.25).abs() < EPSILON);
+        assert!((barycentrics[2] - 0.5).abs() < EPSILON);
+
+        let back = Ray { origin: Point3::new(0.25, 0.5, -1.0), direction: Vector3::unit_z() };
+        assert!((back.intersect_triangle(triangle, None).unwrap().0 - 1.0).abs() < EPSILON);
+        assert_eq!(back.intersect_triangle(triangle, Some(wgpu::Face::Back)), None);
+        assert_eq!(front.intersect_triangle(triangle, Some(wgpu::Face::Front)), None);
+
+        let outside = Ray { origin: Point3::new(0.75, 0.75, 3.0), direction: -Vector3::unit_z() };
+        assert_eq!(outside.intersect_triangle(triangle, None), None);
+        let behind = Ray { origin: Point3::new(0.25, 0.5, 3.0), direction: Vector3::unit_z() };
+        assert_eq!(behind.intersect_triangle(triangle, None), None);
+        let parallel = Ray { origin: Point3::new(-1.0, 0.25, 0.0), direction: Vector3::unit_x() };
+        assert_eq!(parallel.intersect_triangle(triangle, None), None);
+    }
+
+    #[test]
+    fn intersects_boxes() {
+        let aabb = Aabb { min: Point3::new(-1.0, -1.0, -1.0), max: Point3::new(1.0, 1.0, 1.0) };
+        let ray = |origin: [f32; 3], direction: [f32; 3]| Ray { origin: origin.into(), direction: Vector3::from(direction).normalize() };
+        assert_eq!(ray([0.0, 0.0, 5.0], [0.0, 0.0, -1.0]).intersect_aabb(&aabb), Some(4.0));
+        assert_eq!(ray([0.0, 0.0, 0.0], [1.0, 0.0, 0.0]).intersect_aabb(&aabb), Some(0.0));
+        assert_eq!(ray([0.0, 2.0, 5.0], [0.0, 0.0, -1.0]).intersect_aabb(&aabb), None);
+        assert_eq!(ray([0.0, 0.0, 5.0], [0.0, 0.0, 1.0]).intersect_aabb(&aabb), None);
+        assert!(ray([5.0, 5.0, 5.0], [-1.0, -1.0, -1.0]).intersect_aabb(&aabb).is_some());
+    }
+
+    #[test]
+    fn finds_the_nearest_triangle() {
+        // Two quads facing +z, at z = 0 and z = 1
+        let positions: Vec<Point3<f32>> = [0.0, 1.0].iter()
+            .flat_map(|z| vec![Point3::new(-1.0, -1.0, *z), Point3::new(1.0, -1.0, *z), Point3::new(1.0, 1.0, *z), Point3::new(-1.0, 1.0, *z)])
+            .collect();
+        let indices = [0, 1, 2, 0, 2, 3, 4, 5, 6, 4, 6, 7];
+        let ray = Ray { origin: Point3::new(0.5, -0.5, 5.0), direction: -Vector3::unit_z() };
+        let (distance, triangle, _) = intersect_triangles(&ray, &positions, &indices, Some(wgpu::Face::Back), f32::INFINITY).unwrap();
+        assert!((distance - 4.0).abs() < EPSILON);
+        assert_eq!(triangle, 2);
+        // Anything closer than max_distance is all that counts
+        assert_eq!(intersect_triangles(&ray, &positions, &indices, Some(wgpu::Face::Back), 3.0), None);
+        // Seen from behind both quads are culled
+        let back = Ray { origin: Point3::new(0.5, -0.5, -5.0), direction: Vector3::unit_z() };
+        assert_eq!(intersect_triangles(&back, &positions, &indices, Some(wgpu::Face::Back), f32::INFINITY), None);
+    }
+
+    #[test]
+    fn transformed_rays_keep_their_distances() {
+        let ray = Ray { origin: Point3::new(0.0, 0.0, 10.0), direction: -Vector3::unit_z() };
+        let model = Matrix4::from_translation(Vector3::new(0.0, 0.0, 2.0)) * Matrix4::from_scale(2.0);
+        let local = ray.transform(model.invert().unwrap());
+        let (distance, _) = local.intersect_triangle([Point3::new(-1.0, -1.0, 0.0), Point3::new(1.0, -1.0, 0.0), Point3::new(0.0, 1.0, 0.0)], None).unwrap();
+        assert!((distance - 8.0).abs() < EPSILON);
+    }
+}
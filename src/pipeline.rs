@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::asset_manager::{AssetManager, Handle};
+
+// Owned version of wgpu::VertexBufferLayout, so it can be stored in a cache key
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct VertexLayout {
+    pub array_stride: wgpu::BufferAddress,
+    pub step_mode: wgpu::InputStepMode,
+    pub attributes: Vec<wgpu::VertexAttribute>,
+}
+
+impl VertexLayout {
+    fn as_desc(&self) -> wgpu::VertexBufferLayout<'_> {
+        wgpu::VertexBufferLayout {
+            array_stride: self.array_stride,
+            step_mode: self.step_mode,
+            attributes: &self.attributes,
+        }
+    }
+}
+
+impl<'a> From<wgpu::VertexBufferLayout<'a>> for VertexLayout {
+    fn from(desc: wgpu::VertexBufferLayout<'a>) -> Self {
+        Self {
+            array_stride: desc.array_stride,
+            step_mode: desc.step_mode,
+            attributes: desc.attributes.to_vec(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DepthSettings {
+    pub format: wgpu::TextureFormat,
+    pub write_enabled: bool,
+    pub compare: wgpu::CompareFunction,
+}
+
+// Everything that makes two pipelines different
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct PipelineKey {
+    // Address of the pipeline layout, which the cache keeps alive
+    layout: usize,
+    shader: u64,
+    fragment_entry_point: Option<String>,
+    vertex_layouts: Vec<VertexLayout>,
+    color_format: wgpu::TextureFormat,
+    blend: Option<wgpu::BlendState>,
+    topology: wgpu::PrimitiveTopology,
+    cull_mode: Option<wgpu::Face>,
+    polygon_mode: wgpu::PolygonMode,
+    depth: Option<DepthSettings>,
+    sample_count: u32,
+}
+
+// Describes a render pipeline with a single color target.
+// The defaults match an opaque, back face culled triangle list without depth testing.
+// Vertex stages are always `main` and front faces wind counter clockwise.
+pub struct PipelineBuilder {
+    label: String,
+    layout: Arc<wgpu::PipelineLayout>,
+    shader: Handle<wgpu::ShaderModule>,
+    key: PipelineKey,
+}
+
+impl PipelineBuilder {
+    pub fn new(
+        label: &str,
+        layout: &Arc<wgpu::PipelineLayout>,
+        shader: &Handle<wgpu::ShaderModule>,
+        color_format: wgpu::TextureFormat
+    ) -> Self {
+        Self {
+            label: label.to_string(),
+            layout: layout.clone(),
+            shader: shader.clone(),
+            key: PipelineKey {
+                layout: Arc::as_ptr(layout) as usize,
+                shader: shader.id(),
+                fragment_entry_point: Some("main".to_string()),
+                vertex_layouts: Vec::new(),
+                color_format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                cull_mode: Some(wgpu::Face::Back),
+                polygon_mode: wgpu::PolygonMode::Fill,
+                depth: None,
+                sample_count: 1,
+            },
+        }
+    }
+
+    // None creates a pipeline without a fragment stage
+    pub fn fragment_entry_point(mut self, entry_point: Option<&str>) -> Self {
+        self.key.fragment_entry_point = entry_point.map(str::to_string);
+        self
+    }
+
+    pub fn vertex_layout<L: Into<VertexLayout>>(mut self, layout: L) -> Self {
+        self.key.vertex_layouts.push(layout.into());
+        self
+    }
+
+    pub fn blend(mut self, blend: Option<wgpu::BlendState>) -> Self {
+        self.key.blend = blend;
+        self
+    }
+
+    pub fn topology(mut self, topology: wgpu::PrimitiveTopology) -> Self {
+        self.key.topology = topology;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: Option<wgpu::Face>) -> Self {
+        self.key.cull_mode = cull_mode;
+        self
+    }
+
+    // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
+    pub fn polygon_mode(mut self, polygon_mode: wgpu::PolygonMode) -> Self {
+        self.key.polygon_mode = polygon_mode;
+        self
+    }
+
+    pub fn depth(mut self, depth: Option<DepthSettings>) -> Self {
+        self.key.depth = depth;
+        self
+    }
+
+    pub fn sample_count(mut self, sample_count: u32) -> Self {
+        self.key.sample_count = sample_count;
+        self
+    }
+
+    // Returns the cached pipeline if one with identical settings has been built before
+    pub fn build(
+        self,
+        device: &wgpu::Device,
+        assets: &AssetManager,
+        cache: &mut PipelineCache
+    ) -> Arc<wgpu::RenderPipeline> {
+        if let Some(cached) = cache.pipelines.get(&self.key) {
+            return cached.pipeline.clone();
+        }
+
+        let key = &self.key;
+        let shader = assets.shader(&self.shader);
+        let vertex_buffers: Vec<wgpu::VertexBufferLayout> = key.vertex_layouts.iter()
+            .map(VertexLayout::as_desc)
+            .collect();
+        let targets = [wgpu::ColorTargetState {
+            format: key.color_format,
+            write_mask: wgpu::ColorWrite::ALL,
+            blend: key.blend,
+        }];
+        let strip_index_format = match key.topology {
+            wgpu::PrimitiveTopology::LineStrip | wgpu::PrimitiveTopology::TriangleStrip => Some(wgpu::IndexFormat::Uint32),
+            _ => None,
+        };
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&self.label),
+            layout: Some(&self.layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "main",
+                buffers: &vertex_buffers,
+            },
+            fragment: key.fragment_entry_point.as_ref().map(|entry_point| wgpu::FragmentState {
+                module: shader,
+                entry_point,
+                targets: &targets,
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: key.topology,
+                strip_index_format,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: key.cull_mode,
+                polygon_mode: key.polygon_mode,
+                clamp_depth: false,
+                conservative: false,
+            },
+            depth_stencil: key.depth.map(|depth| wgpu::DepthStencilState {
+                format: depth.format,
+                depth_write_enabled: depth.write_enabled,
+                depth_compare: depth.compare,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: key.sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        });
+
+        let pipeline = Arc::new(pipeline);
+        cache.pipelines.insert(self.key, CachedPipeline {
+            pipeline: pipeline.clone(),
+            _layout: self.layout,
+            _shader: self.shader,
+        });
+        pipeline
+    }
+}
+
+struct CachedPipeline {
+    pipeline: Arc<wgpu::RenderPipeline>,
+    // Keeps the key's layout address from being reused and the shader from being freed
+    _layout: Arc<wgpu::PipelineLayout>,
+    _shader: Handle<wgpu::ShaderModule>,
+}
+
+#[derive(Default)]
+pub struct PipelineCache {
+    pipelines: HashMap<PipelineKey, CachedPipeline>,
+}
+
+impl PipelineCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
@@ -0,0 +1,486 @@
+use std::sync::Arc;
+
+use anyhow::*;
+
+use crate::asset_manager::{AssetManager, Handle};
+use crate::bloom::Bloom;
+use crate::pipeline::{PipelineBuilder, PipelineCache};
+use crate::texture::{self, Texture};
+
+pub const TONEMAP: &str = "Tonemap";
+pub const COLOR_GRADING: &str = "Color Grading";
+pub const VIGNETTE: &str = "Vignette";
+pub const GAMMA: &str = "Gamma";
+
+const LUT_SIZE: u32 = 16;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TonemapParams {
+    pub exposure: f32,
+    // A TonemapOperator
+    pub operator: u32,
+}
+
+// How HDR colors are compressed into 0..1, the values match shaders/post/tonemap.wgsl
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TonemapOperator {
+    Reinhard = 0,
+    // Narkowicz's fit of the ACES filmic curve
+    Aces = 1,
+    // Hable's filmic curve from Uncharted 2
+    Uncharted2 = 2,
+}
+
+impl TonemapOperator {
+    pub const ALL: [TonemapOperator; 3] = [TonemapOperator::Reinhard, TonemapOperator::Aces, TonemapOperator::Uncharted2];
+
+    pub fn from_u32(value: u32) -> Option<Self> {
+        Self::ALL.iter().copied().find(|operator| *operator as u32 == value)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TonemapOperator::Reinhard => "Reinhard",
+            TonemapOperator::Aces => "ACES",
+            TonemapOperator::Uncharted2 => "Uncharted 2",
+        }
+    }
+}
+
+impl TonemapParams {
+    pub fn new(operator: TonemapOperator, exposure: f32) -> Self {
+        Self { exposure, operator: operator as u32 }
+    }
+
+    pub fn operator(&self) -> TonemapOperator {
+        TonemapOperator::from_u32(self.operator).unwrap_or(TonemapOperator::Reinhard)
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ColorGradingParams {
+    // 0 leaves colors unchanged, 1 fully applies the lookup table
+    pub strength: f32,
+    pub lut_size: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct VignetteParams {
+    pub intensity: f32,
+    pub radius: f32,
+    pub softness: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GammaParams {
+    pub gamma: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct HeatMapParams {
+    // Fragments per pixel shown in white, fewer go through red, yellow, green and blue to black
+    pub max_count: f32,
+}
+
+// A full-screen pass reading the output of the previous one.
+// Effect shaders provide a vertex and fragment entry point called main, the vertex one usually
+// from shaders/include/fullscreen.wgsl, and bind
+//   group 0, binding 0: the input, texture_2d<f32>
+//   group 0, binding 1: its sampler
+//   group 0, binding 2: the effect's parameters, a uniform block
+//   group 1, binding 0 and 1: an optional extra texture and sampler, e.g. a lookup table
+// See shaders/post for examples.
+pub struct PostEffect {
+    pub name: String,
+    pub enabled: bool,
+    params: Vec<u8>,
+    params_buffer: wgpu::Buffer,
+    // One per chain target it may read from
+    input_bind_groups: Vec<wgpu::BindGroup>,
+    extra_bind_group: Option<wgpu::BindGroup>,
+    // Writing into another intermediate target, or into the chain's output
+    intermediate_pipeline: Arc<wgpu::RenderPipeline>,
+    output_pipeline: Arc<wgpu::RenderPipeline>,
+}
+
+impl PostEffect {
+    pub fn params<T: bytemuck::Pod>(&self) -> T {
+        let mut params = T::zeroed();
+        let size = std::mem::size_of::<T>();
+        bytemuck::bytes_of_mut(&mut params).copy_from_slice(&self.params[..size]);
+        params
+    }
+
+    pub fn set_params<T: bytemuck::Pod>(&mut self, params: T) {
+        let bytes = bytemuck::bytes_of(&params);
+        self.params[..bytes.len()].copy_from_slice(bytes);
+    }
+}
+
+// Runs the scene through a chain of post effects. The scene is rendered into scene_view in
+// HDR, bloom is added onto it and then every enabled effect reads the previous result, the
+// last one writing to the output.
+pub struct PostProcessChain {
+    effects: Vec<PostEffect>,
+    bloom: Bloom,
+    // Ping pong targets, the scene is rendered into the first one
+    targets: [Texture; 2],
+    input_layout: wgpu::BindGroupLayout,
+    output_format: wgpu::TextureFormat,
+    // Used when no effect is enabled
+    copy: PostEffect,
+    // Colors the overdraw counts of RenderMode::Overdraw, see run_heat_map
+    heat_map: PostEffect,
+    // Keeps the color grading lookup table alive
+    lut: Texture,
+}
+
+impl PostProcessChain {
+    // Creates the chain with the built in tonemap, color grading, vignette and gamma effects
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        assets: &mut AssetManager,
+        cache: &mut PipelineCache,
+        sc_desc: &wgpu::SwapChainDescriptor,
+        output_format: wgpu::TextureFormat
+    ) -> Result<Self> {
+        let targets = Self::create_targets(device, sc_desc);
+        let input_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                        filtering: true,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("post_effect_input_bind_group_layout"),
+        });
+
+        let lut = Texture::color_lut(device, queue, LUT_SIZE, &texture::identity_lut(LUT_SIZE)?, "Color Grading LUT");
+        let copy = Self::create_effect(device, assets, cache, &targets, &input_layout, output_format, "Copy", "shaders/blit.wgsl", &[0; 4], None)?;
+        let heat_map = Self::create_effect(
+            device, assets, cache, &targets, &input_layout, output_format,
+            "Heat Map", "shaders/post/heat_map.wgsl", bytemuck::bytes_of(&HeatMapParams { max_count: 8.0 }), None,
+        )?;
+        let bloom = Bloom::new(device, assets, cache, sc_desc, &targets[0])?;
+
+        let mut chain = Self {
+            effects: Vec::new(),
+            bloom,
+            targets,
+            input_layout,
+            output_format,
+            copy,
+            heat_map,
+            lut,
+        };
+
+        chain.add_effect(device, assets, cache, TONEMAP, "shaders/post/tonemap.wgsl", TonemapParams::new(TonemapOperator::Aces, 1.0))?;
+        let lut_params = ColorGradingParams { strength: 1.0, lut_size: LUT_SIZE as f32 };
+        let effect = Self::create_effect(
+            device, assets, cache, &chain.targets, &chain.input_layout, chain.output_format,
+            COLOR_GRADING, "shaders/post/color_grading.wgsl", bytemuck::bytes_of(&lut_params),
+            Some((&chain.lut, wgpu::TextureViewDimension::D3)),
+        )?;
+        chain.effects.push(effect);
+        chain.add_effect(device, assets, cache, VIGNETTE, "shaders/post/vignette.wgsl", VignetteParams { intensity: 0.5, radius: 0.6, softness: 0.5 })?;
+        chain.add_effect(device, assets, cache, GAMMA, "shaders/post/gamma.wgsl", GammaParams { gamma: 1.0 })?;
+
+        Ok(chain)
+    }
+
+    fn create_targets(device: &wgpu::Device, sc_desc: &wgpu::SwapChainDescriptor) -> [Texture; 2] {
+        [
+            Texture::create_frame_texture(device, sc_desc, Texture::HDR_FORMAT, "post_process_target_0"),
+            Texture::create_frame_texture(device, sc_desc, Texture::HDR_FORMAT, "post_process_target_1"),
+        ]
+    }
+
+    fn create_input_bind_groups(
+        device: &wgpu::Device,
+        targets: &[Texture; 2],
+        layout: &wgpu::BindGroupLayout,
+        params_buffer: &wgpu::Buffer
+    ) -> Vec<wgpu::BindGroup> {
+        targets.iter()
+            .map(|target| device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&target.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&target.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: params_buffer.as_entire_binding(),
+                    },
+                ],
+                label: Some("post_effect_input_bind_group"),
+            }))
+            .collect()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_effect(
+        device: &wgpu::Device,
+        assets: &mut AssetManager,
+        cache: &mut PipelineCache,
+        targets: &[Texture; 2],
+        input_layout: &wgpu::BindGroupLayout,
+        output_format: wgpu::TextureFormat,
+        name: &str,
+        shader_path: &str,
+        params: &[u8],
+        extra_texture: Option<(&Texture, wgpu::TextureViewDimension)>
+    ) -> Result<PostEffect> {
+        let shader: Handle<wgpu::ShaderModule> = assets.load_shader(device, shader_path)?;
+
+        // Uniform buffers are laid out in 16 byte chunks
+        let size = params.len().max(1).div_ceil(16) * 16;
+        let mut padded_params = params.to_vec();
+        padded_params.resize(size, 0);
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("{} Params Buffer", name)),
+            size: size as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let input_bind_groups = Self::create_input_bind_groups(device, targets, input_layout, &params_buffer);
+
+        let extra = extra_texture.map(|(texture, view_dimension)| {
+            let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            comparison: false,
+                            filtering: true,
+                        },
+                        count: None,
+                    },
+                ],
+                label: Some("post_effect_extra_bind_group_layout"),
+            });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&texture.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                    },
+                ],
+                label: Some("post_effect_extra_bind_group"),
+            });
+            (layout, bind_group)
+        });
+
+        let mut bind_group_layouts = vec![input_layout];
+        if let Some((layout, _)) = &extra {
+            bind_group_layouts.push(layout);
+        }
+        let pipeline_layout = Arc::new(
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(&format!("{} Pipeline Layout", name)),
+                bind_group_layouts: &bind_group_layouts,
+                push_constant_ranges: &[],
+            })
+        );
+        let pipeline = |format| {
+            PipelineBuilder::new(&format!("{} Pipeline", name), &pipeline_layout, &shader, format)
+                .cull_mode(None)
+        };
+        let intermediate_pipeline = pipeline(Texture::HDR_FORMAT).build(device, assets, cache);
+        let output_pipeline = pipeline(output_format).build(device, assets, cache);
+
+        Ok(PostEffect {
+            name: name.to_string(),
+            enabled: true,
+            params: padded_params,
+            params_buffer,
+            input_bind_groups,
+            extra_bind_group: extra.map(|(_, bind_group)| bind_group),
+            intermediate_pipeline,
+            output_pipeline,
+        })
+    }
+
+    // Appends a custom effect to the end of the chain, see PostEffect for what the shader has to provide
+    pub fn add_effect<T: bytemuck::Pod>(
+        &mut self,
+        device: &wgpu::Device,
+        assets: &mut AssetManager,
+        cache: &mut PipelineCache,
+        name: &str,
+        shader_path: &str,
+        params: T
+    ) -> Result<&mut PostEffect> {
+        let effect = Self::create_effect(device, assets, cache, &self.targets, &self.input_layout, self.output_format, name, shader_path, bytemuck::bytes_of(&params), None)?;
+        self.effects.push(effect);
+        Ok(self.effects.last_mut().unwrap())
+    }
+
+    // Replaces the color grading lookup table with a strip of size slices, see texture::lut_from_strip
+    pub fn load_lut(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        assets: &mut AssetManager,
+        cache: &mut PipelineCache,
+        img: &image::DynamicImage
+    ) -> Result<()> {
+        let (size, texels) = texture::lut_from_strip(img)?;
+        self.lut = Texture::color_lut(device, queue, size, &texels, "Color Grading LUT");
+
+        let index = self.effects.iter().position(|effect| effect.name == COLOR_GRADING)
+            .ok_or_else(|| anyhow!("There's no color grading effect"))?;
+        let old = &self.effects[index];
+        let params = ColorGradingParams { lut_size: size as f32, ..old.params() };
+        let mut effect = Self::create_effect(
+            device, assets, cache, &self.targets, &self.input_layout, self.output_format,
+            COLOR_GRADING, "shaders/post/color_grading.wgsl", bytemuck::bytes_of(&params),
+            Some((&self.lut, wgpu::TextureViewDimension::D3)),
+        )?;
+        effect.enabled = old.enabled;
+        self.effects[index] = effect;
+        Ok(())
+    }
+
+    pub fn effects_mut(&mut self) -> &mut [PostEffect] {
+        &mut self.effects
+    }
+
+    pub fn effect_mut(&mut self, name: &str) -> Option<&mut PostEffect> {
+        self.effects.iter_mut().find(|effect| effect.name == name)
+    }
+
+    pub fn bloom_mut(&mut self) -> &mut Bloom {
+        &mut self.bloom
+    }
+
+    // Where the scene should be rendered to, in Texture::HDR_FORMAT
+    pub fn scene_view(&self) -> &wgpu::TextureView {
+        &self.targets[0].view
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, sc_desc: &wgpu::SwapChainDescriptor) {
+        // Without `..` a new field doesn't compile until it's listed here, so no effect keeps
+        // reading the old targets
+        let Self { effects, bloom, targets, input_layout, output_format: _, copy, heat_map, lut: _ } = self;
+        *targets = Self::create_targets(device, sc_desc);
+        bloom.resize(device, sc_desc, &targets[0]);
+        let all_effects = effects.iter_mut().chain(std::iter::once(copy)).chain(std::iter::once(heat_map));
+        for effect in all_effects {
+            effect.input_bind_groups = Self::create_input_bind_groups(device, targets, input_layout, &effect.params_buffer);
+        }
+    }
+
+    // Runs every enabled effect, the last one writing into output
+    pub fn run(&self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, output: &wgpu::TextureView) {
+        if self.bloom.enabled {
+            self.bloom.run(queue, encoder, &self.targets[0].view);
+        }
+
+        let mut enabled: Vec<&PostEffect> = self.effects.iter().filter(|effect| effect.enabled).collect();
+        if enabled.is_empty() {
+            enabled.push(&self.copy);
+        }
+        self.run_effects(queue, encoder, &enabled, output);
+    }
+
+    // Copies the scene into output as it is, for debug views that shouldn't be tonemapped
+    pub fn run_copy(&self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, output: &wgpu::TextureView) {
+        self.run_effects(queue, encoder, &[&self.copy], output);
+    }
+
+    // Shows the red channel of the scene, which has to hold fragment counts, as a heat map
+    pub fn run_heat_map(&mut self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, output: &wgpu::TextureView, params: HeatMapParams) {
+        self.heat_map.set_params(params);
+        self.run_effects(queue, encoder, &[&self.heat_map], output);
+    }
+
+    fn run_effects(&self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, effects: &[&PostEffect], output: &wgpu::TextureView) {
+        let mut input = 0;
+        for (i, effect) in effects.iter().enumerate() {
+            queue.write_buffer(&effect.params_buffer, 0, &effect.params);
+
+            let last = i == effects.len() - 1;
+            let (target, pipeline) = if last {
+                (output, &effect.output_pipeline)
+            } else {
+                (&self.targets[1 - input].view, &effect.intermediate_pipeline)
+            };
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(&effect.name),
+                color_attachments: &[
+                    wgpu::RenderPassColorAttachment {
+                        view: target,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                            store: true,
+                        }
+                    }
+                ],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, &effect.input_bind_groups[input], &[]);
+            if let Some(extra) = &effect.extra_bind_group {
+                render_pass.set_bind_group(1, extra, &[]);
+            }
+            render_pass.draw(0..3, 0..1);
+
+            input = 1 - input;
+        }
+    }
+}
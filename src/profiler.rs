@@ -0,0 +1,292 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::*;
+use futures::executor::block_on;
+
+// Two timestamps per scope
+const MAX_SCOPES: u32 = 64;
+
+#[derive(Debug, Clone)]
+pub struct ScopeTiming {
+    pub name: String,
+    // Nesting level, 0 for scopes that aren't inside another one
+    pub depth: usize,
+    // Relative to the start of the profiler
+    pub start: Duration,
+    pub duration: Duration,
+}
+
+struct Scope {
+    name: String,
+    depth: usize,
+    cpu_start: Instant,
+    cpu_end: Option<Instant>,
+}
+
+struct TraceEvent {
+    name: String,
+    frame: u64,
+    start: Duration,
+    duration: Duration,
+}
+
+// Timestamp queries, only available with Features::TIMESTAMP_QUERY
+struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    // Resolved timestamps, mapped for reading after the frame has been submitted
+    buffer: wgpu::Buffer,
+    // Nanoseconds per timestamp tick
+    period: f64,
+    // First timestamp ever seen, so GPU times start at 0 like the CPU ones
+    origin: Option<u64>,
+}
+
+// Times named scopes of each frame, on the GPU when the device supports timestamp queries and
+// on the CPU otherwise. CPU timings only measure how long it took to record the commands.
+//
+//     profiler.begin_scope("Render Pass", &mut encoder);
+//     ... encoder.begin_render_pass(...) ...
+//     profiler.end_scope(&mut encoder);
+//     profiler.resolve(&mut encoder);
+//     queue.submit(...);
+//     profiler.end_frame(&device);
+pub struct Profiler {
+    gpu: Option<GpuTimer>,
+    start: Instant,
+    scopes: Vec<Scope>,
+    // None for scopes dropped past MAX_SCOPES, so end_scope still closes the right one
+    open_scopes: Vec<Option<usize>>,
+    frame: u64,
+    last_frame: Vec<ScopeTiming>,
+    // Every scope of every frame, only kept when a trace is going to be written
+    trace: Option<Vec<TraceEvent>>,
+}
+
+impl Profiler {
+    // The features to request on the device for GPU timing, if the adapter supports them
+    pub fn required_features(adapter: &wgpu::Adapter) -> wgpu::Features {
+        adapter.features() & wgpu::Features::TIMESTAMP_QUERY
+    }
+
+    // record_trace keeps the timings of all frames for write_chrome_trace
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, record_trace: bool) -> Self {
+        let gpu = if device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+                ty: wgpu::QueryType::Timestamp,
+                count: MAX_SCOPES * 2,
+            });
+            let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Profiler Timestamp Buffer"),
+                size: (MAX_SCOPES * 2 * wgpu::QUERY_SIZE) as wgpu::BufferAddress,
+                usage: wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ,
+                mapped_at_creation: false,
+            });
+            Some(GpuTimer {
+                query_set,
+                buffer,
+                period: queue.get_timestamp_period() as f64,
+                origin: None,
+            })
+        } else {
+            log::info!("Timestamp queries are not supported, profiling on the CPU");
+            None
+        };
+
+        Self {
+            gpu,
+            start: Instant::now(),
+            scopes: Vec::new(),
+            open_scopes: Vec::new(),
+            frame: 0,
+            last_frame: Vec::new(),
+            trace: if record_trace { Some(Vec::new()) } else { None },
+        }
+    }
+
+    pub fn begin_scope(&mut self, name: &str, encoder: &mut wgpu::CommandEncoder) {
+        let index = self.scopes.len();
+        if index as u32 >= MAX_SCOPES {
+            log::warn!("Too many profiler scopes, ignoring {}", name);
+            self.open_scopes.push(None);
+            return;
+        }
+        if let Some(gpu) = &self.gpu {
+            encoder.write_timestamp(&gpu.query_set, index as u32 * 2);
+        }
+        self.scopes.push(Scope {
+            name: name.to_string(),
+            depth: self.open_scopes.len(),
+            cpu_start: Instant::now(),
+            cpu_end: None,
+        });
+        self.open_scopes.push(Some(index));
+    }
+
+    pub fn end_scope(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let index = match self.open_scopes.pop() {
+            Some(Some(index)) => index,
+            _ => return,
+        };
+        if let Some(gpu) = &self.gpu {
+            encoder.write_timestamp(&gpu.query_set, index as u32 * 2 + 1);
+        }
+        self.scopes[index].cpu_end = Some(Instant::now());
+    }
+
+    // Copies this frame's timestamps into the readback buffer, call on the last encoder of the frame
+    pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if let Some(gpu) = &self.gpu {
+            if !self.scopes.is_empty() {
+                encoder.resolve_query_set(&gpu.query_set, 0..self.scopes.len() as u32 * 2, &gpu.buffer, 0);
+            }
+        }
+    }
+
+    // Collects the timings of the frame, call after submitting. Waits for the GPU to finish the
+    // frame when timing on the GPU, so profiling costs some parallelism between CPU and GPU.
+    pub fn end_frame(&mut self, device: &wgpu::Device) {
+        while let Some(open) = self.open_scopes.pop() {
+            if let Some(index) = open {
+                log::warn!("Profiler scope {} was never ended", self.scopes[index].name);
+            }
+        }
+
+        let timings = match self.gpu.as_mut() {
+            Some(gpu) if !self.scopes.is_empty() => Self::read_gpu_timings(gpu, device, &self.scopes),
+            _ => self.scopes.iter()
+                .map(|scope| ScopeTiming {
+                    name: scope.name.clone(),
+                    depth: scope.depth,
+                    start: scope.cpu_start - self.start,
+                    duration: scope.cpu_end.unwrap_or(scope.cpu_start) - scope.cpu_start,
+                })
+                .collect(),
+        };
+
+        let frame = self.frame;
+        if let Some(trace) = &mut self.trace {
+            trace.extend(timings.iter().map(|timing| TraceEvent {
+                name: timing.name.clone(),
+                frame,
+                start: timing.start,
+                duration: timing.duration,
+            }));
+        }
+        self.last_frame = timings;
+        self.scopes.clear();
+        self.frame += 1;
+    }
+
+    fn read_gpu_timings(gpu: &mut GpuTimer, device: &wgpu::Device, scopes: &[Scope]) -> Vec<ScopeTiming> {
+        let slice = gpu.buffer.slice(..(scopes.len() as u32 * 2 * wgpu::QUERY_SIZE) as wgpu::BufferAddress);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        if let Err(e) = block_on(mapping) {
+            log::error!("Failed to read back timestamps: {:?}", e);
+            return Vec::new();
+        }
+
+        let timings = {
+            let data = slice.get_mapped_range();
+            let timestamps: &[u64] = bytemuck::cast_slice(&data);
+            let origin = *gpu.origin.get_or_insert(timestamps[0]);
+            let to_duration = |ticks: u64| Duration::from_nanos((ticks as f64 * gpu.period) as u64);
+
+            scopes.iter().enumerate()
+                .map(|(i, scope)| {
+                    let start = timestamps[i * 2];
+                    let end = timestamps[i * 2 + 1];
+                    ScopeTiming {
+                        name: scope.name.clone(),
+                        depth: scope.depth,
+                        start: to_duration(start.saturating_sub(origin)),
+                        duration: to_duration(end.saturating_sub(start)),
+                    }
+                })
+                .collect()
+        };
+        gpu.buffer.unmap();
+        timings
+    }
+
+    // Summary of the last frame, one scope per line
+    pub fn report(&self) -> String {
+        self.last_frame.iter()
+            .map(|timing| format!("{}{}: {:.3} ms", "  ".repeat(timing.depth), timing.name, timing.duration.as_secs_f64() * 1000.0))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // Writes every recorded scope in the Chrome trace event format, viewable in chrome://tracing
+    // or https://ui.perfetto.dev. Requires the profiler to be created with record_trace.
+    pub fn write_chrome_trace(&self, path: &Path) -> Result<()> {
+        let trace = self.trace.as_ref()
+            .ok_or_else(|| anyhow!("The profiler wasn't recording a trace"))?;
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create trace file {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        write_chrome_trace(&mut writer, trace, self.gpu.is_some())?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+fn write_chrome_trace<W: Write>(writer: &mut W, events: &[TraceEvent], gpu: bool) -> Result<()> {
+    let thread = if gpu { "GPU" } else { "CPU" };
+    writeln!(writer, "{{\"traceEvents\":[")?;
+    writeln!(writer, "{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":0,\"tid\":0,\"args\":{{\"name\":\"{}\"}}}}", thread)?;
+    for event in events {
+        writeln!(
+            writer,
+            ",{{\"name\":\"{}\",\"cat\":\"{}\",\"ph\":\"X\",\"pid\":0,\"tid\":0,\"ts\":{:.3},\"dur\":{:.3},\"args\":{{\"frame\":{}}}}}",
+            escape_json(&event.name),
+            thread,
+            event.start.as_secs_f64() * 1e6,
+            event.duration.as_secs_f64() * 1e6,
+            event.frame,
+        )?;
+    }
+    writeln!(writer, "]}}")?;
+    Ok(())
+}
+
+fn escape_json(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_chrome_trace_events() {
+        let events = [TraceEvent {
+            name: "Render \"Pass\"".to_string(),
+            frame: 3,
+            start: Duration::from_micros(1500),
+            duration: Duration::from_micros(250),
+        }];
+        let mut output = Vec::new();
+        write_chrome_trace(&mut output, &events, true).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.starts_with("{\"traceEvents\":["));
+        assert!(output.contains("\"name\":\"Render \\\"Pass\\\"\""));
+        assert!(output.contains("\"ph\":\"X\""));
+        assert!(output.contains("\"ts\":1500.000,\"dur\":250.000"));
+        assert!(output.contains("\"frame\":3"));
+        assert!(output.trim_end().ends_with("]}"));
+    }
+}
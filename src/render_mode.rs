@@ -0,0 +1,342 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use anyhow::*;
+use wgpu::util::DeviceExt;
+
+use crate::asset_manager::{AssetManager, Handle, LoadedAssets};
+use crate::camera::Camera;
+use crate::material::Material;
+use crate::mesh::Mesh;
+use crate::pipeline::{DepthSettings, PipelineBuilder, PipelineCache};
+use crate::scene::SceneObject;
+use crate::texture::Texture;
+use crate::vertex::Vertex;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RenderMode {
+    // The regular lit and post processed scene
+    Shaded,
+    Wireframe,
+    // World space normals, mapped from -1..1 to 0..1
+    Normals,
+    // Texture coordinates in red and green
+    TexCoords,
+    // Linear depth between the near and far plane
+    Depth,
+    // How many fragments were drawn into each pixel, as a heat map
+    Overdraw,
+}
+
+impl RenderMode {
+    pub const ALL: [RenderMode; 6] = [
+        RenderMode::Shaded,
+        RenderMode::Wireframe,
+        RenderMode::Normals,
+        RenderMode::TexCoords,
+        RenderMode::Depth,
+        RenderMode::Overdraw,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RenderMode::Shaded => "Shaded",
+            RenderMode::Wireframe => "Wireframe",
+            RenderMode::Normals => "Normals",
+            RenderMode::TexCoords => "UVs",
+            RenderMode::Depth => "Depth",
+            RenderMode::Overdraw => "Overdraw",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        let index = Self::ALL.iter().position(|mode| mode == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct RenderModeParams {
+    near: f32,
+    far: f32,
+    _padding: [f32; 2],
+}
+
+const ADDITIVE: wgpu::BlendState = wgpu::BlendState {
+    color: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    },
+    alpha: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    },
+};
+
+struct Pipelines {
+    wireframe: Arc<wgpu::RenderPipeline>,
+    normals: Arc<wgpu::RenderPipeline>,
+    tex_coords: Arc<wgpu::RenderPipeline>,
+    depth: Arc<wgpu::RenderPipeline>,
+    overdraw: Arc<wgpu::RenderPipeline>,
+}
+
+// Non indexed copy of a mesh, so every triangle has its own corners to put barycentric
+// coordinates on
+struct TriangleSoup {
+    vertex_buffer: wgpu::Buffer,
+    vertex_count: u32,
+}
+
+// Draws the scene in the debug views of RenderMode, replacing the regular shading in the scene
+// pass. Their output is meant to skip post processing, see PostProcessChain::run_copy and
+// PostProcessChain::run_heat_map.
+pub struct RenderModes {
+    pub max_overdraw: f32,
+    // Whether the adapter can rasterize lines for the wireframe, see optional_features
+    line_wireframe: bool,
+    layout: Arc<wgpu::PipelineLayout>,
+    shader: Handle<wgpu::ShaderModule>,
+    pipelines: Pipelines,
+    params_buffer: wgpu::Buffer,
+    params_bind_group: wgpu::BindGroup,
+    // By mesh id, for the wireframe without line rasterization
+    triangle_soups: HashMap<u64, TriangleSoup>,
+}
+
+impl RenderModes {
+    // Features to request when creating the device, if the adapter has them
+    pub fn optional_features(adapter: &wgpu::Adapter) -> wgpu::Features {
+        adapter.features() & wgpu::Features::NON_FILL_POLYGON_MODE
+    }
+
+    // Takes the layouts of Material::create_bind_group_layout and the object uniforms
+    pub fn new(
+        device: &wgpu::Device,
+        assets: &mut AssetManager,
+        cache: &mut PipelineCache,
+        material_layout: &wgpu::BindGroupLayout,
+        uniform_layout: &wgpu::BindGroupLayout,
+        sample_count: u32
+    ) -> Result<Self> {
+        let shader: Handle<wgpu::ShaderModule> = assets.load_shader(device, "shaders/render_modes.wgsl")?;
+        let params_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("render_mode_bind_group_layout"),
+        });
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Render Mode Params Buffer"),
+            size: std::mem::size_of::<RenderModeParams>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let params_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &params_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("render_mode_bind_group"),
+        });
+        // Same groups as the regular pipelines, with the parameters taking the environment's place
+        let layout = Arc::new(
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Render Mode Pipeline Layout"),
+                bind_group_layouts: &[material_layout, uniform_layout, &params_layout],
+                push_constant_ranges: &[],
+            })
+        );
+        let line_wireframe = device.features().contains(wgpu::Features::NON_FILL_POLYGON_MODE);
+        let pipelines = Self::build_pipelines(device, assets, cache, &layout, &shader, line_wireframe, sample_count);
+
+        Ok(Self {
+            max_overdraw: 8.0,
+            line_wireframe,
+            layout,
+            shader,
+            pipelines,
+            params_buffer,
+            params_bind_group,
+            triangle_soups: HashMap::new(),
+        })
+    }
+
+    fn build_pipelines(
+        device: &wgpu::Device,
+        assets: &AssetManager,
+        cache: &mut PipelineCache,
+        layout: &Arc<wgpu::PipelineLayout>,
+        shader: &Handle<wgpu::ShaderModule>,
+        line_wireframe: bool,
+        sample_count: u32
+    ) -> Pipelines {
+        let builder = |label: &str, entry_point: &str| {
+            PipelineBuilder::new(label, layout, shader, Texture::HDR_FORMAT)
+                .vertex_layout(Vertex::desc())
+                .fragment_entry_point(Some(entry_point))
+                .depth(Some(DepthSettings {
+                    format: Texture::DEPTH_FORMAT,
+                    write_enabled: true,
+                    compare: wgpu::CompareFunction::Less,
+                }))
+                .sample_count(sample_count)
+        };
+
+        // Back faces are part of the wireframe too
+        let wireframe = if line_wireframe {
+            builder("Wireframe Pipeline", "wireframe")
+                .polygon_mode(wgpu::PolygonMode::Line)
+                .cull_mode(None)
+        } else {
+            builder("Wireframe Pipeline", "wireframe_barycentric")
+                .cull_mode(None)
+        };
+        // Every fragment counts, not just the ones that end up visible
+        let overdraw = builder("Overdraw Pipeline", "overdraw")
+            .depth(Some(DepthSettings {
+                format: Texture::DEPTH_FORMAT,
+                write_enabled: false,
+                compare: wgpu::CompareFunction::Always,
+            }))
+            .blend(Some(ADDITIVE));
+
+        Pipelines {
+            wireframe: wireframe.build(device, assets, cache),
+            normals: builder("Normals Pipeline", "normals").build(device, assets, cache),
+            tex_coords: builder("Tex Coords Pipeline", "tex_coords").build(device, assets, cache),
+            depth: builder("Depth Pipeline", "depth").build(device, assets, cache),
+            overdraw: overdraw.build(device, assets, cache),
+        }
+    }
+
+    // Call when the sample count of the scene pass changes
+    pub fn rebuild_pipelines(&mut self, device: &wgpu::Device, assets: &AssetManager, cache: &mut PipelineCache, sample_count: u32) {
+        self.pipelines = Self::build_pipelines(device, assets, cache, &self.layout, &self.shader, self.line_wireframe, sample_count);
+    }
+
+    fn pipeline(&self, mode: RenderMode) -> Option<&wgpu::RenderPipeline> {
+        match mode {
+            RenderMode::Shaded => None,
+            RenderMode::Wireframe => Some(&self.pipelines.wireframe),
+            RenderMode::Normals => Some(&self.pipelines.normals),
+            RenderMode::TexCoords => Some(&self.pipelines.tex_coords),
+            RenderMode::Depth => Some(&self.pipelines.depth),
+            RenderMode::Overdraw => Some(&self.pipelines.overdraw),
+        }
+    }
+
+    // Call before draw every frame. Builds the triangle soups the wireframe needs without line
+    // rasterization, and rebuilds those of meshes that finished loading.
+    #[allow(clippy::too_many_arguments)]
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        mode: RenderMode,
+        camera: &Camera,
+        objects: &[SceneObject],
+        assets: &AssetManager,
+        loaded: &LoadedAssets
+    ) {
+        let (near, far) = camera.depth_range();
+        let params = RenderModeParams { near, far, _padding: [0.0; 2] };
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
+
+        if mode != RenderMode::Wireframe || self.line_wireframe {
+            self.triangle_soups.clear();
+            return;
+        }
+        let used: HashSet<u64> = objects.iter().map(|object| object.mesh.id()).collect();
+        self.triangle_soups.retain(|id, _| used.contains(id));
+        for object in objects {
+            if loaded.contains_mesh(&object.mesh) || !self.triangle_soups.contains_key(&object.mesh.id()) {
+                let soup = Self::create_triangle_soup(device, assets.mesh(&object.mesh));
+                self.triangle_soups.insert(object.mesh.id(), soup);
+            }
+        }
+    }
+
+    fn create_triangle_soup(device: &wgpu::Device, mesh: &Mesh) -> TriangleSoup {
+        // Only the positions are used by the wireframe
+        let vertices: Vec<Vertex> = mesh.indices.iter()
+            .map(|index| Vertex {
+                position: mesh.positions[*index as usize].into(),
+                tex_coords: [0.0; 2],
+                normal: [0.0; 3],
+                tangent: [0.0; 4],
+            })
+            .collect();
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Triangle Soup Vertex Buffer"),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsage::VERTEX,
+        });
+        TriangleSoup { vertex_buffer, vertex_count: vertices.len() as u32 }
+    }
+
+    // Draws the objects in draw_order in a debug view, does nothing for RenderMode::Shaded
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        mode: RenderMode,
+        draw_order: &[usize],
+        objects: &'a [SceneObject],
+        materials: &'a [Material],
+        assets: &'a AssetManager
+    ) {
+        let pipeline = match self.pipeline(mode) {
+            Some(pipeline) => pipeline,
+            None => return,
+        };
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(2, &self.params_bind_group, &[]);
+        for &index in draw_order {
+            let object = &objects[index];
+            render_pass.set_bind_group(0, &materials[object.material].bind_group, &[]);
+            render_pass.set_bind_group(1, &object.uniform_bind_group, &[]);
+            match self.triangle_soups.get(&object.mesh.id()) {
+                Some(soup) => {
+                    render_pass.set_vertex_buffer(0, soup.vertex_buffer.slice(..));
+                    render_pass.draw(0..soup.vertex_count, 0..1);
+                }
+                None => {
+                    let mesh = assets.mesh(&object.mesh);
+                    render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                    render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                    render_pass.draw_indexed(0..mesh.num_indices, 0, 0..1);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_cycles_through_every_mode() {
+        let mut mode = RenderMode::Shaded;
+        for expected in RenderMode::ALL.iter().skip(1) {
+            mode = mode.next();
+            assert_eq!(mode, *expected);
+        }
+        assert_eq!(mode.next(), RenderMode::Shaded);
+    }
+}
@@ -0,0 +1,88 @@
+use std::cmp::Ordering;
+
+use cgmath::{EuclideanSpace, InnerSpace, Vector3};
+
+use crate::asset_manager::{AssetManager, Handle};
+use crate::camera::Camera;
+use crate::culling::{Aabb, BoundingSphere, Frustum};
+use crate::lod::{LodGroup, LodMetric};
+use crate::material::Material;
+use crate::mesh::Mesh;
+use crate::transformation_matrix::TransformationMatrix;
+
+// Mirrored by shaders/include/object_uniforms.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ObjectUniforms {
+    pub mvp: [[f32; 4]; 4],
+    pub model: [[f32; 4]; 4],
+    // World space, w is unused
+    pub camera_position: [f32; 4],
+    // Light::radiance
+    pub light: [f32; 4],
+    pub alpha_cutoff: f32,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub normal_scale: f32,
+    // See id_buffer::object_id
+    pub object_id: u32,
+    pub _padding: [u32; 3],
+}
+
+pub struct SceneObject {
+    pub name: String,
+    pub transform: TransformationMatrix,
+    // The mesh drawn this frame, picked from lod when there is one
+    pub mesh: Handle<Mesh>,
+    pub lod: Option<LodGroup>,
+    // Index into the materials of the scene
+    pub material: usize,
+    pub uniform_buffer: wgpu::Buffer,
+    pub uniform_bind_group: wgpu::BindGroup,
+}
+
+impl SceneObject {
+    pub fn world_position(&self) -> Vector3<f32> {
+        self.transform.compute_transformation_matrix().w.truncate()
+    }
+
+    // Switches mesh to the level of detail for the camera, see LodGroup::select
+    pub fn update_lod(&mut self, camera: &Camera, assets: &AssetManager, hysteresis: f32) {
+        let lod = match &mut self.lod {
+            Some(lod) => lod,
+            None => return,
+        };
+        // Measured on the base mesh, so switching levels doesn't change the measurement
+        let model = self.transform.compute_transformation_matrix();
+        let sphere = assets.mesh(lod.base_mesh()).bounds.bounding_sphere().transform(model);
+        let value = match lod.metric() {
+            LodMetric::Distance => (sphere.center.to_vec() - camera.position()).magnitude(),
+            LodMetric::ScreenSize => camera.screen_size(sphere.center, sphere.radius),
+        };
+        self.mesh = lod.select(value, hysteresis).clone();
+    }
+
+    // The world space volumes in_frustum tests
+    pub fn culling_bounds(&self, mesh: &Mesh) -> (BoundingSphere, Aabb) {
+        let model = self.transform.compute_transformation_matrix();
+        (mesh.bounds.bounding_sphere().transform(model), mesh.bounds.transform(model))
+    }
+
+    // Tests the cheap bounding sphere first, the box is tighter around long and thin meshes
+    pub fn in_frustum(&self, frustum: &Frustum, mesh: &Mesh) -> bool {
+        let (sphere, aabb) = self.culling_bounds(mesh);
+        frustum.intersects_sphere(&sphere) && frustum.intersects_aabb(&aabb)
+    }
+}
+
+// Order in which objects have to be drawn: everything opaque first, followed by the transparent
+// objects sorted back to front so blending composites them correctly
+pub fn draw_order(objects: &[SceneObject], materials: &[Material], camera_position: Vector3<f32>) -> Vec<usize> {
+    let is_transparent = |i: &usize| materials[objects[*i].material].alpha_mode.is_transparent();
+    let distance = |i: usize| (objects[i].world_position() - camera_position).magnitude2();
+
+    let (mut order, mut transparent): (Vec<usize>, Vec<usize>) = (0..objects.len()).partition(|i| !is_transparent(i));
+    transparent.sort_by(|a, b| distance(*b).partial_cmp(&distance(*a)).unwrap_or(Ordering::Equal));
+    order.extend(transparent);
+    order
+}
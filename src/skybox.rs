@@ -0,0 +1,165 @@
+use std::sync::Arc;
+
+use anyhow::*;
+use cgmath::{Matrix4, SquareMatrix};
+
+use crate::asset_manager::{AssetManager, Handle};
+use crate::pipeline::{DepthSettings, PipelineBuilder, PipelineCache};
+use crate::texture::Texture;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct SkyboxUniforms {
+    inv_view_projection: [[f32; 4]; 4],
+}
+
+// Environment cube map drawn behind everything in the scene pass. It's drawn on the far plane
+// without writing depth, so objects drawn before or after it cover it.
+pub struct Skybox {
+    // Kept alive for the bind group
+    _cube: Texture,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    pipeline_layout: Arc<wgpu::PipelineLayout>,
+    shader: Handle<wgpu::ShaderModule>,
+    pipeline: Arc<wgpu::RenderPipeline>,
+    pub visible: bool,
+}
+
+impl Skybox {
+    // `cube` has to be a cube map, see Texture::cube_from_dir and Texture::cube_from_equirectangular
+    pub fn new(
+        device: &wgpu::Device,
+        assets: &mut AssetManager,
+        cache: &mut PipelineCache,
+        cube: Texture,
+        color_format: wgpu::TextureFormat,
+        sample_count: u32
+    ) -> Result<Self> {
+        let shader: Handle<wgpu::ShaderModule> = assets.load_shader(device, "shaders/skybox.wgsl")?;
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Skybox Uniform Buffer"),
+            size: std::mem::size_of::<SkyboxUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                        filtering: true,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("skybox_bind_group_layout"),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&cube.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&cube.sampler),
+                },
+            ],
+            label: Some("skybox_bind_group"),
+        });
+        let pipeline_layout = Arc::new(
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Skybox Pipeline Layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            })
+        );
+        let pipeline = Self::build_pipeline(device, assets, cache, &pipeline_layout, &shader, color_format, sample_count);
+
+        Ok(Self {
+            _cube: cube,
+            uniform_buffer,
+            bind_group,
+            pipeline_layout,
+            shader,
+            pipeline,
+            visible: true,
+        })
+    }
+
+    fn build_pipeline(
+        device: &wgpu::Device,
+        assets: &AssetManager,
+        cache: &mut PipelineCache,
+        layout: &Arc<wgpu::PipelineLayout>,
+        shader: &Handle<wgpu::ShaderModule>,
+        color_format: wgpu::TextureFormat,
+        sample_count: u32
+    ) -> Arc<wgpu::RenderPipeline> {
+        PipelineBuilder::new("Skybox Pipeline", layout, shader, color_format)
+            .cull_mode(None)
+            // The triangle sits exactly on the far plane the depth buffer is cleared to
+            .depth(Some(DepthSettings {
+                format: Texture::DEPTH_FORMAT,
+                write_enabled: false,
+                compare: wgpu::CompareFunction::LessEqual,
+            }))
+            .sample_count(sample_count)
+            .build(device, assets, cache)
+    }
+
+    // Call when the format or sample count of the scene pass changes
+    pub fn rebuild_pipeline(
+        &mut self,
+        device: &wgpu::Device,
+        assets: &AssetManager,
+        cache: &mut PipelineCache,
+        color_format: wgpu::TextureFormat,
+        sample_count: u32
+    ) {
+        self.pipeline = Self::build_pipeline(device, assets, cache, &self.pipeline_layout, &self.shader, color_format, sample_count);
+    }
+
+    // Takes the camera's view projection without translation, see Camera::build_skybox_view_projection_matrix
+    pub fn prepare(&self, queue: &wgpu::Queue, view_projection: Matrix4<f32>) {
+        if let Some(inverse) = view_projection.invert() {
+            let uniforms = SkyboxUniforms { inv_view_projection: inverse.into() };
+            queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniforms]));
+        }
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
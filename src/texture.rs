@@ -1,4 +1,7 @@
+use std::fs::File;
+use std::io::BufReader;
 use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
 
 use image::GenericImageView;
 use anyhow::*;
@@ -10,14 +13,210 @@ pub struct Texture {
 }
 
 impl Texture {
-    pub fn from_bytes(
+    pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+    // The scene is rendered in linear HDR and only brought into displayable range by post processing
+    pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+    // The sRGB variant of an 8 bit color format, so tonemapped linear colors are encoded on write
+    pub fn srgb_format(format: wgpu::TextureFormat) -> wgpu::TextureFormat {
+        match format {
+            wgpu::TextureFormat::Bgra8Unorm => wgpu::TextureFormat::Bgra8UnormSrgb,
+            wgpu::TextureFormat::Rgba8Unorm => wgpu::TextureFormat::Rgba8UnormSrgb,
+            other => other,
+        }
+    }
+
+    // The sample count has to match the color attachment it's used with
+    pub fn create_depth_texture(device: &wgpu::Device, sc_desc: &wgpu::SwapChainDescriptor, sample_count: u32, label: &str) -> Self {
+        let size = wgpu::Extent3d {
+            width: sc_desc.width,
+            height: sc_desc.height,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(
+            &wgpu::TextureDescriptor {
+                label: Some(label),
+                size,
+                mip_level_count: 1,
+                sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format: Self::DEPTH_FORMAT,
+                usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+            }
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(
+            &wgpu::SamplerDescriptor {
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Nearest,
+                compare: Some(wgpu::CompareFunction::LessEqual),
+                lod_min_clamp: -100.0,
+                lod_max_clamp: 100.0,
+                ..Default::default()
+            }
+        );
+
+        Self { texture, view, sampler }
+    }
+
+    // Multisampled color target, resolved into the swap chain frame at the end of the pass
+    pub fn create_multisampled_framebuffer(device: &wgpu::Device, sc_desc: &wgpu::SwapChainDescriptor, format: wgpu::TextureFormat, sample_count: u32) -> wgpu::TextureView {
+        let multisampled_frame_descriptor = &wgpu::TextureDescriptor {
+            label: Some("multisampled_framebuffer"),
+            size: wgpu::Extent3d {
+                width: sc_desc.width,
+                height: sc_desc.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
+        };
+
+        device
+            .create_texture(multisampled_frame_descriptor)
+            .create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    // Offscreen color target the size of the swap chain, which unlike the swap chain can be
+    // sampled and copied from
+    pub fn create_frame_texture(device: &wgpu::Device, sc_desc: &wgpu::SwapChainDescriptor, format: wgpu::TextureFormat, label: &str) -> Self {
+        let texture = device.create_texture(
+            &wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: sc_desc.width,
+                    height: sc_desc.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_SRC,
+            }
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        // Linear filtering lets post effects sample between pixels
+        let sampler = device.create_sampler(
+            &wgpu::SamplerDescriptor {
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Nearest,
+                ..Default::default()
+            }
+        );
+
+        Self { texture, view, sampler }
+    }
+
+    // 3D lookup table of size^3 RGBA texels, with red varying fastest and blue slowest
+    pub fn color_lut(device: &wgpu::Device, queue: &wgpu::Queue, size: u32, texels: &[u8], label: &str) -> Self {
+        let extent = wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: size,
+        };
+        let texture = device.create_texture(
+            &wgpu::TextureDescriptor {
+                label: Some(label),
+                size: extent,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D3,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+            }
+        );
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            texels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(4 * size),
+                rows_per_image: NonZeroU32::new(size),
+            },
+            extent,
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(
+            &wgpu::SamplerDescriptor {
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Nearest,
+                ..Default::default()
+            }
+        );
+
+        Self { texture, view, sampler }
+    }
+
+    // Magenta and black checkerboard used in place of textures that failed to load
+    pub fn checkerboard(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        label: &str
+    ) -> Self {
+        const SIZE: u32 = 64;
+        const CELL: u32 = 8;
+        let img = image::RgbaImage::from_fn(SIZE, SIZE, |x, y| {
+            if (x / CELL) & 1 == (y / CELL) & 1 {
+                image::Rgba([255, 0, 255, 255])
+            } else {
+                image::Rgba([0, 0, 0, 255])
+            }
+        });
+        Self::from_image(device, queue, &image::DynamicImage::ImageRgba8(img), Some(label))
+            .expect("Checkerboard texture is always valid")
+    }
+
+    pub fn solid_color(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        bytes: &[u8],
+        color: [u8; 4],
         label: &str
+    ) -> Self {
+        let img = image::RgbaImage::from_pixel(1, 1, image::Rgba(color));
+        Self::from_image(device, queue, &image::DynamicImage::ImageRgba8(img), Some(label))
+            .expect("Solid color texture is always valid")
+    }
+
+    // Normal maps store directions rather than colors, so they're loaded without sRGB decoding
+    pub fn normal_map_from_path(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: &Path
     ) -> Result<Self> {
-        let img = image::load_from_memory(bytes)?;
-        Self::from_image(device, queue, &img, Some(label))
+        let img = image::open(path)
+            .with_context(|| format!("Failed to load normal map {}", path.display()))?;
+        Self::from_image_with_format(device, queue, &img, path.to_str(), wgpu::TextureFormat::Rgba8Unorm)
+    }
+
+    // Normal map that leaves the surface normal unchanged
+    pub fn flat_normal_map(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let img = image::RgbaImage::from_pixel(1, 1, image::Rgba([128, 128, 255, 255]));
+        Self::from_image_with_format(device, queue, &image::DynamicImage::ImageRgba8(img), Some("flat normal map"), wgpu::TextureFormat::Rgba8Unorm)
+            .expect("Flat normal map is always valid")
     }
 
     pub fn from_image(
@@ -26,7 +225,17 @@ impl Texture {
         img: &image::DynamicImage,
         label: Option<&str>
     ) -> Result<Self> {
-        let rgba = img.as_rgba8().unwrap();
+        Self::from_image_with_format(device, queue, img, label, wgpu::TextureFormat::Rgba8UnormSrgb)
+    }
+
+    fn from_image_with_format(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        label: Option<&str>,
+        format: wgpu::TextureFormat
+    ) -> Result<Self> {
+        let rgba = img.to_rgba8();
         let dimensions = img.dimensions();
 
         let size = wgpu::Extent3d {
@@ -41,7 +250,7 @@ impl Texture {
                 mip_level_count: 1,
                 sample_count: 1,
                 dimension: wgpu::TextureDimension::D2,
-                format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                format,
                 usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
             }
         );
@@ -52,7 +261,7 @@ impl Texture {
                 mip_level: 0,
                 origin: wgpu::Origin3d::ZERO,
             },
-            rgba,
+            &rgba,
             wgpu::ImageDataLayout {
                 offset: 0,
                 bytes_per_row: NonZeroU32::new(4 * dimensions.0),
@@ -76,4 +285,367 @@ impl Texture {
 
         Ok(Self { texture, view, sampler })
     }
-}
\ No newline at end of file
+
+    // Cube map from six square images of the same size, in CUBE_FACES order
+    pub fn cube_from_images(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        faces: &[image::DynamicImage],
+        label: &str
+    ) -> Result<Self> {
+        if faces.len() != 6 {
+            bail!("A cube map needs 6 faces, got {}", faces.len());
+        }
+        let (size, _) = faces[0].dimensions();
+        let mut texels = Vec::with_capacity((size * size * 4 * 6) as usize);
+        for (face, img) in CUBE_FACES.iter().zip(faces) {
+            if img.dimensions() != (size, size) {
+                let (width, height) = img.dimensions();
+                bail!("Cube map face {} is {}x{}, expected {}x{}", face, width, height, size, size);
+            }
+            texels.extend_from_slice(&img.to_rgba8());
+        }
+        Ok(Self::cube(device, queue, size, wgpu::TextureFormat::Rgba8UnormSrgb, 4, &texels, label))
+    }
+
+    // Loads dir/px.png, dir/nx.png, ... with any extension the image crate supports
+    pub fn cube_from_dir(device: &wgpu::Device, queue: &wgpu::Queue, dir: &Path) -> Result<Self> {
+        let faces = CUBE_FACES.iter()
+            .map(|face| {
+                let path = find_cube_face(dir, face)?;
+                image::open(&path).with_context(|| format!("Failed to load cube map face {}", path.display()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Self::cube_from_images(device, queue, &faces, &dir.to_string_lossy())
+    }
+
+    // Projects an equirectangular (latitude/longitude) panorama onto a cube map with faces of
+    // face_size pixels. Radiance .hdr files keep their full range, other images are treated as sRGB.
+    pub fn cube_from_equirectangular(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: &Path,
+        face_size: u32
+    ) -> Result<Self> {
+        let panorama = load_linear_image(path)
+            .with_context(|| format!("Failed to load environment map {}", path.display()))?;
+        let texels = equirectangular_to_cube(&panorama, face_size);
+        let bytes: Vec<u8> = texels.iter()
+            .flat_map(|value| f32_to_f16(*value).to_le_bytes().to_vec())
+            .collect();
+        Ok(Self::cube(device, queue, face_size, Self::HDR_FORMAT, 8, &bytes, &path.to_string_lossy()))
+    }
+
+    // 1x1 HDR cube map of a single linear color, an environment that lights evenly from everywhere
+    pub fn uniform_cube(device: &wgpu::Device, queue: &wgpu::Queue, color: [f32; 3], label: &str) -> Self {
+        let [r, g, b] = color;
+        let texel: Vec<u8> = [r, g, b, 1.0].iter()
+            .flat_map(|value| f32_to_f16(*value).to_le_bytes().to_vec())
+            .collect();
+        Self::cube(device, queue, 1, Self::HDR_FORMAT, 8, &texel.repeat(6), label)
+    }
+
+    fn cube(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        size: u32,
+        format: wgpu::TextureFormat,
+        bytes_per_texel: u32,
+        texels: &[u8],
+        label: &str
+    ) -> Self {
+        let extent = wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 6,
+        };
+        let texture = device.create_texture(
+            &wgpu::TextureDescriptor {
+                label: Some(label),
+                size: extent,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+            }
+        );
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            texels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(bytes_per_texel * size),
+                rows_per_image: NonZeroU32::new(size),
+            },
+            extent,
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(
+            &wgpu::SamplerDescriptor {
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Nearest,
+                ..Default::default()
+            }
+        );
+
+        Self { texture, view, sampler }
+    }
+}
+
+// Texels of a lookup table that maps every color to itself
+pub fn identity_lut(size: u32) -> Result<Vec<u8>> {
+    if size < 2 {
+        bail!("Lookup tables need at least 2 entries per channel, got {}", size);
+    }
+    let mut texels = Vec::with_capacity((size * size * size * 4) as usize);
+    let scale = 255.0 / (size - 1) as f32;
+    for b in 0..size {
+        for g in 0..size {
+            for r in 0..size {
+                texels.extend_from_slice(&[
+                    (r as f32 * scale).round() as u8,
+                    (g as f32 * scale).round() as u8,
+                    (b as f32 * scale).round() as u8,
+                    255,
+                ]);
+            }
+        }
+    }
+    Ok(texels)
+}
+
+// Converts a lookup table stored as a horizontal strip of size slices, each size x size with
+// red along x and green along y, into texels for Texture::color_lut. Returns the size.
+pub fn lut_from_strip(img: &image::DynamicImage) -> Result<(u32, Vec<u8>)> {
+    let (width, height) = img.dimensions();
+    if height < 2 {
+        bail!("Lookup table strips need at least 2 entries per channel, got {}", height);
+    }
+    if width != height * height {
+        bail!("Expected a {0}x{1} lookup table strip, got {2}x{1}", height * height, height, width);
+    }
+    let rgba = img.to_rgba8();
+    let size = height;
+    let mut texels = Vec::with_capacity((size * size * size * 4) as usize);
+    for b in 0..size {
+        for g in 0..size {
+            for r in 0..size {
+                texels.extend_from_slice(&rgba.get_pixel(b * size + r, g).0);
+            }
+        }
+    }
+    Ok((size, texels))
+}
+
+// Cube map layers in the order wgpu expects them: +x, -x, +y, -y, +z, -z
+pub const CUBE_FACES: [&str; 6] = ["px", "nx", "py", "ny", "pz", "nz"];
+
+fn find_cube_face(dir: &Path, face: &str) -> Result<PathBuf> {
+    let entries = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read cube map directory {}", dir.display()))?;
+    entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .find(|path| path.file_stem().is_some_and(|stem| stem == face))
+        .ok_or_else(|| anyhow!("Cube map face {} not found in {}", face, dir.display()))
+}
+
+// Linear RGB float pixels
+pub struct LinearImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[f32; 3]>,
+}
+
+fn load_linear_image(path: &Path) -> Result<LinearImage> {
+    let is_hdr = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("hdr"));
+    if is_hdr {
+        let decoder = image::codecs::hdr::HdrDecoder::new(BufReader::new(File::open(path)?))?;
+        let meta = decoder.metadata();
+        let pixels = decoder.read_image_hdr()?.into_iter().map(|pixel| pixel.0).collect();
+        Ok(LinearImage { width: meta.width, height: meta.height, pixels })
+    } else {
+        let rgb = image::open(path)?.to_rgb8();
+        let pixels = rgb.pixels()
+            .map(|pixel| [srgb_to_linear(pixel[0]), srgb_to_linear(pixel[1]), srgb_to_linear(pixel[2])])
+            .collect();
+        Ok(LinearImage { width: rgb.width(), height: rgb.height(), pixels })
+    }
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let c = value as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// Direction through texel coordinates u, v in 0..1 of a cube face, following the usual cube
+// map convention with v pointing down. cube_direction in shaders/ibl/common.wgsl has to match.
+pub fn cube_face_direction(face: usize, u: f32, v: f32) -> [f32; 3] {
+    let s = u * 2.0 - 1.0;
+    let t = v * 2.0 - 1.0;
+    let [x, y, z] = match face {
+        0 => [1.0, -t, -s],
+        1 => [-1.0, -t, s],
+        2 => [s, 1.0, t],
+        3 => [s, -1.0, -t],
+        4 => [s, -t, 1.0],
+        _ => [-s, -t, -1.0],
+    };
+    let length = (x * x + y * y + z * z).sqrt();
+    [x / length, y / length, z / length]
+}
+
+// Where a normalized direction lands on an equirectangular panorama, u wraps around the y axis
+// starting at -x and v goes from straight up at 0 to straight down at 1
+pub fn equirectangular_uv(direction: [f32; 3]) -> (f32, f32) {
+    let [x, y, z] = direction;
+    let u = z.atan2(x) / (2.0 * std::f32::consts::PI) + 0.5;
+    let v = y.clamp(-1.0, 1.0).acos() / std::f32::consts::PI;
+    (u, v)
+}
+
+// RGBA float texels of the six faces in CUBE_FACES order, sampled bilinearly from the panorama
+fn equirectangular_to_cube(panorama: &LinearImage, face_size: u32) -> Vec<f32> {
+    let mut texels = Vec::with_capacity((face_size * face_size * 4 * 6) as usize);
+    let texel = |x: i64, y: i64| {
+        let x = x.rem_euclid(panorama.width as i64) as u32;
+        let y = y.clamp(0, panorama.height as i64 - 1) as u32;
+        panorama.pixels[(y * panorama.width + x) as usize]
+    };
+    for face in 0..6 {
+        for y in 0..face_size {
+            for x in 0..face_size {
+                let direction = cube_face_direction(face, (x as f32 + 0.5) / face_size as f32, (y as f32 + 0.5) / face_size as f32);
+                let (u, v) = equirectangular_uv(direction);
+                let px = u * panorama.width as f32 - 0.5;
+                let py = v * panorama.height as f32 - 0.5;
+                let (x0, y0) = (px.floor(), py.floor());
+                let (fx, fy) = (px - x0, py - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+                let top = lerp(texel(x0, y0), texel(x0 + 1, y0), fx);
+                let bottom = lerp(texel(x0, y0 + 1), texel(x0 + 1, y0 + 1), fx);
+                let [r, g, b] = lerp(top, bottom, fy);
+                texels.extend_from_slice(&[r, g, b, 1.0]);
+            }
+        }
+    }
+    texels
+}
+
+fn lerp(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t, a[2] + (b[2] - a[2]) * t]
+}
+
+// Half precision float bits, rounding to nearest and saturating to infinity
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        // Infinity or NaN
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if exponent <= 0 {
+        // Subnormal, or too small and flushed to zero
+        if exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        let half = (mantissa >> shift) as u16;
+        let round = ((mantissa >> (shift - 1)) & 1) as u16;
+        return sign | (half + round);
+    }
+    let half = sign | ((exponent as u16) << 10) | (mantissa >> 13) as u16;
+    // Rounding can carry into the exponent, which is still correct
+    half + ((mantissa >> 12) & 1) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identity_strip_matches_identity_lut() {
+        let size = 4;
+        let scale = 255.0 / (size - 1) as f32;
+        let strip = image::RgbaImage::from_fn(size * size, size, |x, y| {
+            let (r, g, b) = (x % size, y, x / size);
+            image::Rgba([(r as f32 * scale).round() as u8, (g as f32 * scale).round() as u8, (b as f32 * scale).round() as u8, 255])
+        });
+        let (lut_size, texels) = lut_from_strip(&image::DynamicImage::ImageRgba8(strip)).unwrap();
+        assert_eq!(lut_size, size);
+        assert_eq!(texels, identity_lut(size).unwrap());
+    }
+
+    #[test]
+    fn rejects_strips_with_the_wrong_shape() {
+        let img = image::DynamicImage::ImageRgba8(image::RgbaImage::new(16, 16));
+        assert!(lut_from_strip(&img).is_err());
+    }
+
+    #[test]
+    fn rejects_lookup_tables_smaller_than_two() {
+        assert!(identity_lut(0).is_err());
+        assert!(identity_lut(1).is_err());
+        for size in 0..2 {
+            let img = image::DynamicImage::ImageRgba8(image::RgbaImage::new(size * size, size));
+            assert!(lut_from_strip(&img).is_err());
+        }
+    }
+
+    #[test]
+    fn converts_to_half_floats() {
+        assert_eq!(f32_to_f16(0.0), 0x0000);
+        assert_eq!(f32_to_f16(1.0), 0x3c00);
+        assert_eq!(f32_to_f16(-2.0), 0xc000);
+        assert_eq!(f32_to_f16(0.5), 0x3800);
+        assert_eq!(f32_to_f16(65504.0), 0x7bff);
+        assert_eq!(f32_to_f16(1.0e6), 0x7c00);
+        // Smallest subnormal
+        assert_eq!(f32_to_f16(5.960_464_5e-8), 0x0001);
+    }
+
+    #[test]
+    fn cube_face_centers_point_along_the_axes() {
+        let expected = [[1.0, 0.0, 0.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, -1.0, 0.0], [0.0, 0.0, 1.0], [0.0, 0.0, -1.0]];
+        for (face, direction) in expected.iter().enumerate() {
+            assert_eq!(cube_face_direction(face, 0.5, 0.5), *direction);
+        }
+        // The top of the side faces looks up
+        assert!(cube_face_direction(4, 0.5, 0.0)[1] > 0.0);
+    }
+
+    #[test]
+    fn equirectangular_poles_and_horizon() {
+        let (_, v) = equirectangular_uv([0.0, 1.0, 0.0]);
+        assert_eq!(v, 0.0);
+        let (_, v) = equirectangular_uv([0.0, -1.0, 0.0]);
+        assert_eq!(v, 1.0);
+        let (u, v) = equirectangular_uv([1.0, 0.0, 0.0]);
+        assert_eq!((u, v), (0.5, 0.5));
+    }
+}
@@ -0,0 +1,143 @@
+use std::time::{Duration, Instant};
+
+// Longest frame we try to catch up on, anything longer is treated as a hitch and dropped
+// so a slow frame can't snowball into ever more simulation steps
+const MAX_FRAME_TIME: Duration = Duration::from_millis(250);
+
+// How often the frame statistics are recomputed
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy)]
+pub struct FrameTick {
+    // Number of fixed updates to run this frame, each advancing the simulation by fixed_timestep
+    pub fixed_steps: u32,
+    pub fixed_timestep: Duration,
+    // How far we are between the last two simulation states, in 0..1, used to interpolate rendering
+    pub alpha: f32,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameStats {
+    pub fps: f32,
+    pub average_frame_time: Duration,
+    pub min_frame_time: Duration,
+    pub max_frame_time: Duration,
+    pub total_frames: u64,
+}
+
+// Drives a fixed timestep simulation from variable rate rendering
+pub struct GameClock {
+    fixed_timestep: Duration,
+    accumulator: Duration,
+    last_frame: Instant,
+    stats: FrameStats,
+    interval_time: Duration,
+    interval_frames: u32,
+    interval_min: Duration,
+    interval_max: Duration,
+}
+
+impl GameClock {
+    pub fn new(fixed_timestep: Duration) -> Self {
+        Self {
+            fixed_timestep,
+            accumulator: Duration::ZERO,
+            last_frame: Instant::now(),
+            stats: FrameStats::default(),
+            interval_time: Duration::ZERO,
+            interval_frames: 0,
+            interval_min: Duration::MAX,
+            interval_max: Duration::ZERO,
+        }
+    }
+
+    pub fn stats(&self) -> &FrameStats {
+        &self.stats
+    }
+
+    // Call once per rendered frame
+    pub fn tick(&mut self) -> FrameTick {
+        let now = Instant::now();
+        let delta = now - self.last_frame;
+        self.last_frame = now;
+        self.advance(delta)
+    }
+
+    pub fn advance(&mut self, delta: Duration) -> FrameTick {
+        self.record(delta);
+
+        self.accumulator += delta.min(MAX_FRAME_TIME);
+        let mut fixed_steps = 0;
+        while self.accumulator >= self.fixed_timestep {
+            self.accumulator -= self.fixed_timestep;
+            fixed_steps += 1;
+        }
+
+        FrameTick {
+            fixed_steps,
+            fixed_timestep: self.fixed_timestep,
+            alpha: self.accumulator.as_secs_f32() / self.fixed_timestep.as_secs_f32(),
+        }
+    }
+
+    fn record(&mut self, delta: Duration) {
+        self.stats.total_frames += 1;
+        self.interval_time += delta;
+        self.interval_frames += 1;
+        self.interval_min = self.interval_min.min(delta);
+        self.interval_max = self.interval_max.max(delta);
+
+        if self.interval_time >= STATS_INTERVAL {
+            self.stats.fps = self.interval_frames as f32 / self.interval_time.as_secs_f32();
+            self.stats.average_frame_time = self.interval_time / self.interval_frames;
+            self.stats.min_frame_time = self.interval_min;
+            self.stats.max_frame_time = self.interval_max;
+
+            self.interval_time = Duration::ZERO;
+            self.interval_frames = 0;
+            self.interval_min = Duration::MAX;
+            self.interval_max = Duration::ZERO;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accumulates_fixed_steps() {
+        let mut clock = GameClock::new(Duration::from_millis(10));
+
+        let tick = clock.advance(Duration::from_millis(25));
+        assert_eq!(tick.fixed_steps, 2);
+        assert!((tick.alpha - 0.5).abs() < 1e-4);
+
+        // The leftover 5ms carries over into the next frame
+        let tick = clock.advance(Duration::from_millis(5));
+        assert_eq!(tick.fixed_steps, 1);
+        assert!(tick.alpha.abs() < 1e-4);
+    }
+
+    #[test]
+    fn long_frames_are_clamped() {
+        let mut clock = GameClock::new(Duration::from_millis(10));
+        let tick = clock.advance(Duration::from_secs(5));
+        assert_eq!(tick.fixed_steps, 25);
+    }
+
+    #[test]
+    fn computes_stats_every_interval() {
+        let mut clock = GameClock::new(Duration::from_millis(10));
+        for _ in 0..40 {
+            clock.advance(Duration::from_millis(20));
+        }
+        clock.advance(Duration::from_millis(200));
+
+        let stats = clock.stats();
+        assert_eq!(stats.total_frames, 41);
+        assert!((stats.fps - 41.0).abs() < 1e-3);
+        assert_eq!(stats.min_frame_time, Duration::from_millis(20));
+        assert_eq!(stats.max_frame_time, Duration::from_millis(200));
+    }
+}
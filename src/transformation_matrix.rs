@@ -1,11 +1,12 @@
-use cgmath::{Angle, Deg, InnerSpace, Matrix4, Point3, Rad, Vector3};
+use cgmath::{EuclideanSpace, Matrix4, Point3, Rad, Vector3, VectorSpace};
 
 #[derive(Debug, Clone)]
 pub struct TransformationMatrix {
-    position: Vector3<f32>,
-    yaw: Rad<f32>,
-    pitch: Rad<f32>,
-    roll: Rad<f32>,
+    pub position: Vector3<f32>,
+    pub yaw: Rad<f32>,
+    pub pitch: Rad<f32>,
+    pub roll: Rad<f32>,
+    pub scale: f32,
 }
 
 impl TransformationMatrix {
@@ -23,6 +24,7 @@ impl TransformationMatrix {
             yaw: yaw.into(),
             pitch: pitch.into(), 
             roll: roll.into(),
+            scale: 1.0,
         }
     }
 
@@ -34,15 +36,13 @@ impl TransformationMatrix {
         let yaw = Matrix4::from_angle_y(self.yaw);
         let roll = Matrix4::from_angle_z(self.roll); 
         let pos = Matrix4::from_translation(self.position);
-        let scale = Matrix4::from_scale(1.0);
+        let scale = Matrix4::from_scale(self.scale);
 
         // Extrinsic rotation
         let rotation = pitch * yaw * roll;
 
-        // Scale, then rotate, then translate
-        let transformation = scale * rotation * pos;
-        return transformation
-        
+        // Scale, then translate, then rotate around the origin
+        rotation * pos * scale
     }
 
     pub fn transform<
@@ -53,7 +53,23 @@ impl TransformationMatrix {
     >(
         &self, movement: V, rotate_pitch: Y, rotate_yaw: P, rotate_roll: R
     ) -> Self {
-        return (*self).clone();
+        let mut transformed = (*self).clone();
+        transformed.position += movement.into().to_vec();
+        transformed.pitch += rotate_pitch.into();
+        transformed.yaw += rotate_yaw.into();
+        transformed.roll += rotate_roll.into();
+        transformed
+    }
+
+    // Linear interpolation of every component, alpha 0 gives self and alpha 1 gives other
+    pub fn lerp(&self, other: &Self, alpha: f32) -> Self {
+        Self {
+            position: self.position.lerp(other.position, alpha),
+            yaw: self.yaw + (other.yaw - self.yaw) * alpha,
+            pitch: self.pitch + (other.pitch - self.pitch) * alpha,
+            roll: self.roll + (other.roll - self.roll) * alpha,
+            scale: self.scale + (other.scale - self.scale) * alpha,
+        }
     }
 
 }
\ No newline at end of file
@@ -1,8 +1,12 @@
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Vertex {
-    position: [f32; 3],
-    tex_coords: [f32; 2], // NEW!
+    pub position: [f32; 3],
+    pub tex_coords: [f32; 2], // NEW!
+    pub normal: [f32; 3],
+    // xyz points along increasing u, w is the handedness: the bitangent along increasing v is
+    // w * cross(normal, tangent)
+    pub tangent: [f32; 4],
 }
 
 impl Vertex {
@@ -22,6 +26,16 @@ impl Vertex {
                     shader_location: 1,
                     format: wgpu::VertexFormat::Float32x2, // NEW!
                 },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
             ]
         }
     }
@@ -29,14 +43,14 @@ impl Vertex {
 
 pub const VERTICES: &[Vertex] = &[
     // Changed
-    Vertex { position: [-0.0868241, 0.49240386, 0.0], tex_coords: [0.4131759, 0.00759614], }, // A
-    Vertex { position: [-0.49513406, 0.06958647, 0.0], tex_coords: [0.0048659444, 0.43041354], }, // B
-    Vertex { position: [-0.21918549, -0.44939706, 0.0], tex_coords: [0.28081453, 0.949397057], }, // C
-    Vertex { position: [0.35966998, -0.3473291, 0.0], tex_coords: [0.85967, 0.84732911], }, // D
-    Vertex { position: [0.44147372, 0.2347359, 0.0], tex_coords: [0.9414737, 0.2652641], }, // E
+    Vertex { position: [-0.0868241, 0.49240386, 0.0], tex_coords: [0.4131759, 0.00759614], normal: [0.0, 0.0, 1.0], tangent: [1.0, 0.0, 0.0, -1.0], }, // A
+    Vertex { position: [-0.49513406, 0.06958647, 0.0], tex_coords: [0.0048659444, 0.43041354], normal: [0.0, 0.0, 1.0], tangent: [1.0, 0.0, 0.0, -1.0], }, // B
+    Vertex { position: [-0.21918549, -0.44939706, 0.0], tex_coords: [0.28081453, 0.9493971], normal: [0.0, 0.0, 1.0], tangent: [1.0, 0.0, 0.0, -1.0], }, // C
+    Vertex { position: [0.35966998, -0.3473291, 0.0], tex_coords: [0.85967, 0.84732911], normal: [0.0, 0.0, 1.0], tangent: [1.0, 0.0, 0.0, -1.0], }, // D
+    Vertex { position: [0.44147372, 0.2347359, 0.0], tex_coords: [0.9414737, 0.2652641], normal: [0.0, 0.0, 1.0], tangent: [1.0, 0.0, 0.0, -1.0], }, // E
 ];
 
-pub const INDICES: &[u16] = &[
+pub const INDICES: &[u32] = &[
     0, 1, 4,
     1, 2, 4,
     2, 3, 4,